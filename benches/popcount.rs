@@ -0,0 +1,26 @@
+//! Times count_ones_masked, which counts set bits one byte at a time. Run it with and without the
+//! popcount_table feature to compare the lookup table with u8::count_ones:
+//!
+//!     cargo bench --bench popcount
+//!     cargo bench --bench popcount --features popcount_table
+use bit_rust::bits::BitRust;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn count_ones_masked(c: &mut Criterion) {
+    let bytes: Vec<u8> = (0..1u32 << 20).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+    let mask: Vec<u8> = bytes.iter().map(|b| b.rotate_left(3)).collect();
+    let a = BitRust::from_bytes(bytes);
+    let m = BitRust::from_bytes(mask);
+    c.bench_function("count_ones_masked aligned", |b| {
+        b.iter(|| black_box(&a).count_ones_masked(black_box(&m)).unwrap())
+    });
+    let length = a.length() - 8;
+    let a = a.getslice(Some(3), Some(3 + length)).unwrap();
+    let m = m.getslice(Some(5), Some(5 + length)).unwrap();
+    c.bench_function("count_ones_masked unaligned", |b| {
+        b.iter(|| black_box(&a).count_ones_masked(black_box(&m)).unwrap())
+    });
+}
+
+criterion_group!(benches, count_ones_masked);
+criterion_main!(benches);
@@ -1,36 +1,351 @@
 use std::fmt;
-use std::sync::Arc;
-use pyo3::{pyclass, pymethods, PyRef, PyResult};
-use pyo3::exceptions::{PyIndexError, PyValueError};
-use hamming;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use pyo3::{pyclass, pymethods, Bound, FromPyObject, Py, PyAny, PyObject, PyRef, PyResult, Python};
+use pyo3::types::{PyAnyMethods, PyBool, PyByteArray, PyByteArrayMethods, PyBytes, PyBytesMethods, PyDict,
+                  PyDictMethods, PyInt, PyList, PySlice, PySliceMethods, PyString, PyTuple, PyType};
+use pyo3::buffer::PyBuffer;
+use pyo3::pyclass::CompareOp;
+use pyo3::exceptions::{PyIndexError, PyTypeError, PyValueError};
 
 /// BitRust is a struct that holds an arbitrary amount of binary data. The data is stored
 /// in a Vec<u8> but does not need to be a multiple of 8 bits. A bit offset and a bit length
 /// are stored.
 /// 
-#[pyclass]
+#[pyclass(module = "bit_rust")]
 pub struct BitRust {
     data: Arc<Vec<u8>>,
     offset: i64,
     length: i64,
+    cache: ReprCache,
+}
+
+/// Lazily computed strings for the Python-facing to_bin, to_hex and repr. Internal code uses
+/// compute_bin and compute_hex directly so doesn't fill these. The only method that changes a
+/// BitRust, set_mutable_slice, replaces the whole struct including its cache, so a cached string
+/// never goes stale. Each new BitRust, including clones, starts empty.
+#[derive(Default)]
+struct ReprCache {
+    bin: OnceLock<String>,
+    hex: OnceLock<String>,
+}
+
+/// The bytes, offset and length used to reconstruct a BitRust with from_state.
+type State = (Vec<u8>, i64, i64);
+
+/// Lookup table giving the number of set bits in each byte value.
+#[cfg(any(feature = "popcount_table", test))]
+static POPCOUNT_TABLE: [u8; 256] = {
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = (i as u8).count_ones() as u8;
+        i += 1;
+    }
+    table
+};
+
+/// Returns the number of set bits in a byte. With the popcount_table feature a lookup table is
+/// used, for targets where count_ones doesn't compile to a popcount instruction.
+#[inline]
+fn byte_count_ones(byte: u8) -> u32 {
+    #[cfg(feature = "popcount_table")]
+    return POPCOUNT_TABLE[byte as usize] as u32;
+    #[cfg(not(feature = "popcount_table"))]
+    byte.count_ones()
+}
+
+/// A string argument that can be given from Python as either a str or as ASCII bytes.
+pub struct StrOrBytes(String);
+
+impl<'py> FromPyObject<'py> for StrOrBytes {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = ob.extract::<String>() {
+            return Ok(StrOrBytes(s));
+        }
+        let bytes = if let Ok(b) = ob.downcast::<PyBytes>() {
+            b.as_bytes().to_vec()
+        } else if let Ok(b) = ob.downcast::<PyByteArray>() {
+            b.to_vec()
+        } else {
+            return Err(PyTypeError::new_err("Expected a str or bytes."));
+        };
+        if !bytes.is_ascii() {
+            return Err(PyValueError::new_err("Bytes must be ASCII."));
+        }
+        Ok(StrOrBytes(String::from_utf8(bytes).unwrap()))
+    }
+}
+
+/// Maximum number of bits shown by the Debug formatting and so by __repr__.
+static REPR_LIMIT: AtomicUsize = AtomicUsize::new(100);
+
+/// Number of times join has used the byte-aligned fast path. Only tracked in debug builds.
+#[cfg(debug_assertions)]
+pub static JOIN_FAST_PATH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Iterator over the bits of a BitRust. Each call to __iter__ creates a new iterator, so a
+/// BitRust can be iterated over any number of times.
+#[pyclass]
+pub struct BitRustIterator {
+    bits: BitRust,
+    index: i64,
+}
+
+#[pymethods]
+impl BitRustIterator {
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(&mut self) -> Option<bool> {
+        if self.index >= self.bits.length {
+            return None;
+        }
+        self.index += 1;
+        Some(self.bits.get_bit(self.index - 1))
+    }
+}
+
+/// Iterator over chunks of the bytes of a BitRust.
+#[pyclass]
+pub struct BytesChunkIterator {
+    bits: BitRust,
+    chunk_bytes: usize,
+    pos: usize,
+}
+
+#[pymethods]
+impl BytesChunkIterator {
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(&mut self) -> Option<Vec<u8>> {
+        let end_byte = self.bits.end_byte();
+        let start = self.bits.start_byte() + self.pos;
+        if start >= end_byte {
+            return None;
+        }
+        let end = std::cmp::min(start + self.chunk_bytes, end_byte);
+        self.pos += self.chunk_bytes;
+        Some(self.bits.data[start..end].to_vec())
+    }
+}
+
+/// Iterator over a hex representation in strings of a fixed number of characters, so that very
+/// large values can be written out without building the whole string.
+#[pyclass]
+pub struct HexChunkIterator {
+    bits: BitRust,
+    chars_per_chunk: usize,
+    pos: i64,
+}
+
+#[pymethods]
+impl HexChunkIterator {
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(&mut self) -> Option<String> {
+        if self.pos >= self.bits.length {
+            return None;
+        }
+        let end = std::cmp::min(self.pos + 4 * self.chars_per_chunk as i64, self.bits.length);
+        let chunk = self.bits.slice(self.pos, end).to_hex().unwrap();
+        self.pos = end;
+        Some(chunk)
+    }
+}
+
+/// Iterator over the positions of a pattern, found lazily one at a time.
+#[pyclass]
+pub struct FindAllIterator {
+    bits: BitRust,
+    pattern: BitRust,
+    bytealigned: bool,
+    start: i64,
+}
+
+#[pymethods]
+impl FindAllIterator {
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(&mut self) -> Option<i64> {
+        let pos = self.start + self.bits.find(&self.pattern, self.start, self.bytealigned)?;
+        self.start = pos + 1;
+        Some(pos)
+    }
+}
+
+/// Builds a BitRust incrementally from chunks of bytes, for example when reading from a stream
+/// whose total size isn't known in advance.
+#[pyclass]
+pub struct BitRustBuilder {
+    data: Vec<u8>,
+}
+
+#[pymethods]
+impl BitRustBuilder {
+    #[new]
+    pub fn new() -> Self {
+        BitRustBuilder { data: Vec::new() }
+    }
+
+    /// Appends a chunk of bytes.
+    pub fn feed_bytes(&mut self, chunk: Vec<u8>) {
+        self.data.extend(chunk);
+    }
+
+    /// Returns a BitRust containing all the bytes fed so far.
+    pub fn build(&self) -> BitRust {
+        BitRust::from_bytes(self.data.clone())
+    }
+}
+
+impl Default for BitRustBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A read-only view on a range of bits in a BitRust. Holds a reference to the Python object rather
+/// than cloning its buffer, so creating many views avoids the atomic refcount on the shared data.
+#[pyclass]
+pub struct BitRustView {
+    source: Py<BitRust>,
+    start: i64,
+    length: i64,
+}
+
+#[pymethods]
+impl BitRustView {
+    /// Returns the length of the view in bits.
+    pub fn length(&self) -> i64 {
+        self.length
+    }
+
+    /// Returns the bool value at a given bit index in the view.
+    pub fn getindex(&self, py: Python<'_>, mut bit_index: i64) -> PyResult<bool> {
+        if bit_index >= self.length || bit_index < -self.length {
+            return Err(PyIndexError::new_err("Out of range."));
+        }
+        if bit_index < 0 {
+            bit_index += self.length;
+        }
+        let source = self.source.borrow(py);
+        self.check_source(&source)?;
+        Ok(source.get_bit(self.start + bit_index))
+    }
+
+    pub fn to_bin(&self, py: Python<'_>) -> PyResult<String> {
+        let source = self.source.borrow(py);
+        self.check_source(&source)?;
+        Ok((self.start..self.start + self.length)
+            .map(|i| if source.get_bit(i) { '1' } else { '0' })
+            .collect())
+    }
+}
+
+impl BitRustView {
+    /// The source can be shortened by set_mutable_slice after the view is created, so the range
+    /// has to be checked against its current length on every access.
+    fn check_source(&self, source: &BitRust) -> PyResult<()> {
+        if self.start + self.length > source.length {
+            return Err(PyIndexError::new_err("The view extends beyond the end of its source."));
+        }
+        Ok(())
+    }
+}
+
+/// Reads from a BitRust sequentially, keeping track of the current bit position.
+#[pyclass]
+pub struct BitRustReader {
+    bits: BitRust,
+    pos: i64,
+}
+
+#[pymethods]
+impl BitRustReader {
+    #[new]
+    pub fn new(bits: &BitRust) -> Self {
+        BitRustReader { bits: bits.clone(), pos: 0 }
+    }
+
+    /// Returns the current bit position.
+    pub fn pos(&self) -> i64 {
+        self.pos
+    }
+
+    /// Reads the next n bits and advances the position.
+    pub fn read(&mut self, n: i64) -> PyResult<BitRust> {
+        if n < 0 || self.pos + n > self.bits.length {
+            return Err(PyValueError::new_err("Not enough bits to read."));
+        }
+        self.pos += n;
+        Ok(self.bits.slice(self.pos - n, self.pos))
+    }
+
+    /// Advances the position to the next multiple of 8 bits, but not past the end, and returns the
+    /// number of bits skipped.
+    pub fn byte_align(&mut self) -> i64 {
+        let aligned = std::cmp::min((self.pos + 7) / 8 * 8, self.bits.length);
+        let skipped = aligned - self.pos;
+        self.pos = aligned;
+        skipped
+    }
+
+    /// Reads an unsigned Exp-Golomb code, as used in H.264 and H.265. The position is unchanged
+    /// if the code can't be read.
+    pub fn read_ue(&mut self) -> PyResult<u64> {
+        let mut zeros = 0;
+        while self.pos + zeros < self.bits.length && !self.bits.get_bit(self.pos + zeros) {
+            zeros += 1;
+        }
+        if zeros > 63 {
+            return Err(PyValueError::new_err("Exp-Golomb code is too large."));
+        }
+        let end = self.pos + 2 * zeros + 1;
+        if end > self.bits.length {
+            return Err(PyValueError::new_err("Not enough bits for Exp-Golomb code."));
+        }
+        let suffix = self.bits.slice(self.pos + zeros + 1, end).to_u64();
+        self.pos = end;
+        Ok((1u64 << zeros) - 1 + suffix)
+    }
+
+    /// Reads a signed Exp-Golomb code. The code numbers 0, 1, 2, 3, 4... map to 0, 1, -1, 2, -2...
+    pub fn read_se(&mut self) -> PyResult<i64> {
+        let k = self.read_ue()?;
+        if k % 2 == 1 {
+            Ok(k.div_ceil(2) as i64)
+        } else {
+            Ok(-((k / 2) as i64))
+        }
+    }
 }
 
 impl fmt::Debug for BitRust {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.length > 100 {
-            return f.debug_struct("Bits")
-                .field("hex", &self.slice(0, 100).to_hex().unwrap())
-                .field("length", &self.length)
-                .finish();
-        }
-        if self.length % 4 == 0 {
+        let limit = REPR_LIMIT.load(Ordering::Relaxed) as i64;
+        let truncated;
+        let shown = if self.length > limit {
+            truncated = self.slice(0, limit);
+            &truncated
+        } else {
+            self
+        };
+        if shown.length % 4 == 0 {
             return f.debug_struct("Bits")
-                .field("hex", &self.to_hex().unwrap())
+                .field("hex", &shown.to_hex().unwrap())
                 .field("length", &self.length)
                 .finish();
         }
         f.debug_struct("Bits")
-            .field("bin", &self.to_bin())
+            .field("bin", &shown.to_bin())
             .field("length", &self.length)
             .finish()
     }
@@ -42,6 +357,7 @@ impl Clone for BitRust {
             data: Arc::clone(&self.data),
             offset: self.offset,
             length: self.length,
+            cache: ReprCache::default(),
         }
     }
 }
@@ -59,19 +375,27 @@ impl PartialEq for BitRust {
                 return false;
             }
         }
-        self.to_bin() == other.to_bin()
+        self.compute_bin() == other.compute_bin()
     }
 }
 
 // Things not part of the Python interface.
 impl BitRust {
+    /// Applies op to each byte. If one operand is a single bit it is repeated to match the length
+    /// of the other, otherwise the lengths must be equal.
     fn bitwise_op<F>(&self, other: &BitRust, op: F) -> Result<Self, ()>
     where F: Fn(u8, u8) -> u8 {
         if self.length != other.length {
+            if other.length == 1 {
+                return self.bitwise_op(&other.broadcast(self.length), op);
+            }
+            if self.length == 1 {
+                return self.broadcast(other.length).bitwise_op(other, op);
+            }
             return Err(());
         }
-        let a = BitRust::from_bin(&self.to_bin()).unwrap();
-        let b = BitRust::from_bin(&other.to_bin()).unwrap();
+        let a = BitRust::from_bin(&self.compute_bin()).unwrap();
+        let b = BitRust::from_bin(&other.compute_bin()).unwrap();
 
         let mut data: Vec<u8> = Vec::new();
         for i in 0..a.data.len() {
@@ -81,28 +405,51 @@ impl BitRust {
             data: Arc::new(data),
             length: self.length,
             offset: 0,
+            cache: ReprCache::default(),
         })
     }
 
-    fn join_internal(bits_vec: &Vec<&BitRust>) -> Self {
+    /// Returns a BitRust of the given length with every bit equal to the first bit of self.
+    fn broadcast(&self, length: i64) -> Self {
+        if self.get_bit(0) { BitRust::from_ones(length) } else { BitRust::from_zeros(length) }
+    }
+
+    fn join_internal(bits_vec: &[&BitRust]) -> Self {
+        // Empty elements contribute nothing, and their offsets shouldn't affect the result.
+        let bits_vec: Vec<&BitRust> = bits_vec.iter().filter(|b| b.length != 0).copied().collect();
         if bits_vec.is_empty() {
             return BitRust::from_zeros(0);
         }
         if bits_vec.len() == 1 {
             return bits_vec[0].clone();
         }
-        let mut data = bits_vec[0].data[bits_vec[0].start_byte()..bits_vec[0].end_byte()].to_vec();
+        let total_length: i64 = bits_vec.iter().map(|b| b.length).sum();
+        // If everything is whole bytes then no bit shifting is needed.
+        if bits_vec.iter().all(|b| b.offset % 8 == 0 && b.length % 8 == 0) {
+            #[cfg(debug_assertions)]
+            JOIN_FAST_PATH_COUNT.fetch_add(1, Ordering::Relaxed);
+            let mut data: Vec<u8> = Vec::with_capacity((total_length / 8) as usize);
+            for bits in &bits_vec {
+                data.extend_from_slice(&bits.data[bits.start_byte()..bits.end_byte()]);
+            }
+            return BitRust {
+                length: data.len() as i64 * 8,
+                data: Arc::new(data),
+                offset: 0,
+                cache: ReprCache::default(),
+            }
+        }
         let new_offset: i64 = bits_vec[0].offset % 8;
+        // Reserve the whole output up front to avoid reallocating as each element is added.
+        let mut data: Vec<u8> = Vec::with_capacity(((new_offset + total_length + 7) / 8) as usize);
+        data.extend_from_slice(&bits_vec[0].data[bits_vec[0].start_byte()..bits_vec[0].end_byte()]);
         let mut new_length: i64 = bits_vec[0].length;
         // Go though the vec of Bits and set the offset of each to the number of bits in the final byte of the previous one
         for bits in &bits_vec[1..] {
-            if bits.length == 0 {
-                continue;
-            }
             let extra_bits = (new_length + new_offset) % 8;
             let offset_bits = bits.copy_with_new_offset(extra_bits);
             if extra_bits == 0 {
-                data.extend(offset_bits.data[offset_bits.start_byte()..offset_bits.end_byte()].to_vec());
+                data.extend_from_slice(&offset_bits.data[offset_bits.start_byte()..offset_bits.end_byte()]);
             }
             else {
                 // Combine last byte of data with first byte of offset_bits.data.
@@ -118,7 +465,117 @@ impl BitRust {
             data: Arc::new(data),
             offset: new_offset,
             length: new_length,
+            cache: ReprCache::default(),
+        }
+    }
+
+    /// Builds the hex string. The length must be a multiple of 4 bits.
+    fn compute_hex(&self) -> String {
+        debug_assert_eq!(self.length % 4, 0);
+        let bit_offset = self.offset % 8;
+        let nibble_offset_data: &Vec<u8> = if bit_offset == 0 || bit_offset == 4 {
+            &self.active_data()
+        } else {
+            &self.copy_with_new_offset(0).data
+        };
+        let x = nibble_offset_data.iter()
+            .map(|byte| format!("{:02x}", byte))
+            .fold(String::new(), |mut acc, hex| {
+                acc.push_str(&hex);
+                acc
+            });
+        if bit_offset == 4 {
+            if self.length % 8 == 0 {
+                return x[1..x.len()-1].to_string();
+            }
+            return x[1..].to_string();
+        }
+        if self.length % 8 == 0 {
+            return x;
+        }
+        debug_assert_eq!(self.length % 8, 4);
+        x[..x.len()-1].to_string()
+    }
+
+    fn compute_bin(&self) -> String {
+        let x = self.data.iter()
+            .map(|byte| format!("{:08b}", byte))
+            .fold(String::new(), |mut bin_str, bin| {
+                bin_str.push_str(&bin);
+                bin_str
+            });
+        x[self.offset as usize..(self.offset + self.length) as usize].to_string()
+    }
+
+    /// Parses a str with a '0x', '0b' or '0o' prefix, which can be in either case. Returns None if
+    /// there's no recognised prefix.
+    fn from_prefixed_str(s: &str) -> Option<PyResult<Self>> {
+        match s.get(..2).map(|p| p.to_lowercase()).as_deref() {
+            Some("0x") => Some(BitRust::from_hex(&s[2..])),
+            Some("0b") => Some(BitRust::from_bin(&s[2..])),
+            Some("0o") => Some(BitRust::from_oct(&s[2..])),
+            _ => None,
+        }
+    }
+
+    /// Appends bits to data, which holds length bits starting at the first bit of the first byte.
+    fn append_to(data: &mut Vec<u8>, length: i64, bits: &BitRust) {
+        if bits.length == 0 {
+            return;
+        }
+        let extra_bits = length % 8;
+        if extra_bits == 0 && bits.offset % 8 == 0 {
+            data.extend_from_slice(&bits.data[bits.start_byte()..bits.end_byte()]);
+            return;
+        }
+        let offset_bits = bits.copy_with_new_offset(extra_bits);
+        if extra_bits == 0 {
+            data.extend_from_slice(&offset_bits.data);
+            return;
+        }
+        // The first extra_bits come from the last byte of data, the rest from offset_bits.
+        let last_byte = data.pop().unwrap() & !(0xff >> extra_bits);
+        data.push(last_byte | (offset_bits.data[0] & (0xff >> extra_bits)));
+        data.extend_from_slice(&offset_bits.data[1..]);
+    }
+
+    fn majority_internal(bits_vec: &[&BitRust]) -> PyResult<Self> {
+        if bits_vec.is_empty() {
+            return Err(PyValueError::new_err("Need at least one BitRust for a majority vote."));
+        }
+        let length = bits_vec[0].length;
+        if bits_vec.iter().any(|b| b.length != length) {
+            return Err(PyValueError::new_err("Lengths do not match."));
+        }
+        Ok(BitRust::from_bools((0..length).map(|i| {
+            let ones = bits_vec.iter().filter(|b| b.get_bit(i)).count();
+            2 * ones > bits_vec.len()
+        })))
+    }
+
+    /// Returns a mask of the same length with bits in the range [start, end) equal to inside and
+    /// the other bits equal to !inside.
+    fn range_mask(&self, start: i64, end: i64, inside: bool) -> PyResult<Self> {
+        if start < 0 || end > self.length || start > end {
+            return Err(PyIndexError::new_err("Invalid range."));
+        }
+        let fill = |length: i64, value: bool| if value { BitRust::from_ones(length) } else { BitRust::from_zeros(length) };
+        Ok(BitRust::join_internal(&[
+            &fill(start, !inside),
+            &fill(end - start, inside),
+            &fill(self.length - end, !inside),
+        ]))
+    }
+
+    /// Returns a BitRust with one bit per row, equal to the parity of the AND of self and that row.
+    fn parity_products(&self, rows: &[&BitRust]) -> PyResult<Self> {
+        if rows.iter().any(|r| r.length != self.length) {
+            return Err(PyValueError::new_err("Each row must have the same length as self."));
         }
+        Ok(BitRust::from_bools(rows.iter().map(|r| {
+            let and = self.bitwise_op(r, |a, b| a & b).unwrap();
+            and.count() % 2 == 1
+        })))
     }
 
     /// Returns the byte index of the start of the binary data.
@@ -131,6 +588,18 @@ impl BitRust {
         ((self.offset + self.length + 7) / 8) as usize
     }
 
+    /// Returns the eight bits of data starting at bit position pos in the data. Any bits before
+    /// the start or after the end of the data are read as zero.
+    fn byte_at(&self, pos: i64) -> u8 {
+        let get = |i: i64| if i >= 0 && (i as usize) < self.data.len() { self.data[i as usize] } else { 0 };
+        let (index, shift) = (pos.div_euclid(8), pos.rem_euclid(8));
+        if shift == 0 {
+            get(index)
+        } else {
+            (get(index) << shift) | (get(index + 1) >> (8 - shift))
+        }
+    }
+
     fn active_data(&self) -> Vec<u8> {
         self.data[self.start_byte()..self.end_byte()].to_vec()
     }
@@ -145,6 +614,7 @@ impl BitRust {
                 data: Arc::new(vec![]),
                 offset: 0,
                 length: 0,
+                cache: ReprCache::default(),
             }
         }
         let byte_offset = (self.offset / 8) as usize;
@@ -154,6 +624,7 @@ impl BitRust {
                 data: Arc::new(self.active_data()),
                 offset: new_offset,
                 length: self.length,
+                cache: ReprCache::default(),
             }
         }
         let old_byte_length = self.end_byte() - self.start_byte();
@@ -192,6 +663,7 @@ impl BitRust {
             data: Arc::new(new_data),
             offset: new_offset,
             length: self.length,
+            cache: ReprCache::default(),
         }
     }
     
@@ -204,7 +676,110 @@ impl BitRust {
             data: Arc::clone(&self.data),
             offset: start_bit + self.offset,
             length: new_length,
+            cache: ReprCache::default(),
+        }
+    }
+
+    /// Interprets as an unsigned integer. Only valid for lengths up to 64 bits.
+    fn to_u64(&self) -> u64 {
+        debug_assert!(self.length <= 64);
+        (0..self.length).fold(0, |acc, i| (acc << 1) | self.get_bit(i) as u64)
+    }
+
+    /// Creates from the lowest length bits of an unsigned integer. Length must be at most 64.
+    fn from_u64(value: u64, length: i64) -> Self {
+        debug_assert!(length <= 64);
+        let bytes = value.to_be_bytes();
+        BitRust::from_bytes(bytes.to_vec()).slice(64 - length, 64).trim()
+    }
+
+    fn uint_op<F>(&self, mask: u64, op: F) -> PyResult<Self>
+    where F: Fn(u64, u64) -> u64 {
+        if self.length > 64 {
+            return Err(PyValueError::new_err("Length must be at most 64 bits."));
+        }
+        if mask.checked_shr(self.length as u32).unwrap_or(0) != 0 {
+            return Err(PyValueError::new_err(format!("Mask can't be represented as an unsigned int of length {}.", self.length)));
+        }
+        Ok(BitRust::from_u64(op(self.to_u64(), mask), self.length))
+    }
+
+    fn extend_left(&self, new_length: i64, value: bool) -> PyResult<Self> {
+        if new_length < self.length {
+            return Err(PyValueError::new_err("New length is shorter than the current length."));
+        }
+        let extra = new_length - self.length;
+        let padding = if value { BitRust::from_ones(extra) } else { BitRust::from_zeros(extra) };
+        Ok(BitRust::join_internal(&[&padding, self]))
+    }
+
+    /// Creates from an iterator of bools, one per bit.
+    fn from_bools<I: Iterator<Item = bool>>(bits: I) -> Self {
+        let mut data: Vec<u8> = Vec::new();
+        let mut length: i64 = 0;
+        for bit in bits {
+            if length % 8 == 0 {
+                data.push(0);
+            }
+            if bit {
+                *data.last_mut().unwrap() |= 128 >> (length % 8);
+            }
+            length += 1;
+        }
+        BitRust {
+            data: Arc::new(data),
+            offset: 0,
+            length,
+            cache: ReprCache::default(),
+        }
+    }
+
+    /// Returns length bits, starting at start and moving step bits each time. The indices must
+    /// all be valid. A step of 1 gives a view on the current data, otherwise the bits are copied.
+    fn getslice_with_step(&self, start: i64, step: i64, length: i64) -> Self {
+        if step == 1 {
+            return self.slice(start, start + length);
+        }
+        BitRust::from_bools((0..length).map(|i| self.get_bit(start + i * step)))
+    }
+
+    /// Returns a new BitRust made from the bits at the given indices, which can be negative.
+    fn gather(&self, indices: &[i64]) -> PyResult<Self> {
+        let mut positive_indices: Vec<i64> = Vec::with_capacity(indices.len());
+        for &index in indices {
+            if index >= self.length || index < -self.length {
+                return Err(PyIndexError::new_err("Out of range."));
+            }
+            positive_indices.push(if index < 0 { index + self.length } else { index });
+        }
+        Ok(BitRust::from_bools(positive_indices.iter().map(|&i| self.get_bit(i))))
+    }
+
+    /// Returns the bit at a given index without bounds checking.
+    fn get_bit(&self, bit_index: i64) -> bool {
+        let p = bit_index + self.offset;
+        self.data[(p / 8) as usize] & (128 >> (p % 8)) != 0
+    }
+
+    /// Returns true if b can be searched for in whole bytes rather than bit by bit.
+    fn can_search_bytes(&self, b: &BitRust) -> bool {
+        self.offset % 8 == 0 && b.offset % 8 == 0 && b.length % 8 == 0 && b.length > 0
+    }
+
+    /// Byte search for the first byte-aligned occurrence of b at or after the bit position start,
+    /// which should be a multiple of 8. Only valid if can_search_bytes is true.
+    fn find_bytes(&self, b: &BitRust, start: i64) -> Option<i64> {
+        debug_assert!(self.can_search_bytes(b));
+        debug_assert!(start % 8 == 0);
+        let first = self.start_byte() + (start / 8) as usize;
+        let last = self.start_byte() + (self.length / 8) as usize;
+        if first >= last {
+            return None;
         }
+        let needle = &b.data[b.start_byte()..b.end_byte()];
+        self.data[first..last].windows(needle.len())
+            .position(|w| w == needle)
+            .map(|p| start + p as i64 * 8)
     }
 
     // Return a new Bits with any excess stored bytes trimmed.
@@ -214,48 +789,344 @@ impl BitRust {
                 data: Arc::clone(&self.data),
                 offset: self.offset,
                 length: self.length,
+                cache: ReprCache::default(),
             }
         }
         BitRust {
             data: Arc::new(self.active_data()),
             offset: self.offset % 8,
             length: self.length,
+            cache: ReprCache::default(),
         }
     }
 
-    // I think this works as a Rust version. Keeping this copy for reference.
-    pub fn find_all_rust<'a>(&'a self, b: &'a BitRust, bytealigned: bool) -> impl Iterator<Item = i64> + 'a {
-        // Use the find fn to find all instances of b in self and return as an iterator
-        let mut start: i64 = 0;
-        std::iter::from_fn(move || {
-            let found = self.find(b, start, bytealigned);
-            match found {
-                Some(x) => {
-                    start = start + x + 1;
-                    Some(start - 1)
+    pub fn from_bin(binary_string: &str) -> PyResult<Self> {
+        BitRust::from_bin_with_chars(binary_string, '0', '1')
+    }
+
+    /// Create from a string of binary digits, using the given characters for the zero and one bits.
+    pub fn from_bin_with_chars(binary_string: &str, zero: char, one: char) -> PyResult<Self> {
+        if zero == one {
+            return Err(PyValueError::new_err("The zero and one characters must be different."));
+        }
+        let chars: Vec<char> = binary_string.chars().collect();
+        let mut data: Vec<u8> = Vec::new();
+        let mut byte: u8 = 0;
+        for chunk in chars.chunks(8) {
+            for (i, &c) in chunk.iter().enumerate() {
+                if c == one {
+                    byte |= 1 << (7 - i);
+                } else if c != zero {
+                    return Err(PyValueError::new_err("Invalid character"));
                 }
-                None => None,
             }
+            data.push(byte);
+            byte = 0;
+        }
+        Ok(BitRust {
+            data: Arc::new(data),
+            offset: 0,
+            length: chars.len() as i64,
+            cache: ReprCache::default(),
         })
     }
 
-}
-
+    /// Decodes straight into the output buffer. An odd final hex digit becomes the top nibble of
+    /// the last byte.
+    pub fn from_hex(hex: &str) -> PyResult<Self> {
+        let hex_bytes = hex.as_bytes();
+        let whole_bytes = hex_bytes.len() / 2;
+        let mut data: Vec<u8> = vec![0; hex_bytes.len().div_ceil(2)];
+        if hex::decode_to_slice(&hex_bytes[..2 * whole_bytes], &mut data[..whole_bytes]).is_err() {
+            return Err(PyValueError::new_err("Invalid character"));
+        }
+        if let Some(&last) = hex_bytes.get(2 * whole_bytes) {
+            match (last as char).to_digit(16) {
+                Some(nibble) => data[whole_bytes] = (nibble as u8) << 4,
+                None => return Err(PyValueError::new_err("Invalid character")),
+            }
+        }
+        Ok(BitRust {
+            data: Arc::new(data),
+            offset: 0,
+            length: hex.len() as i64 * 4,
+            cache: ReprCache::default(),
+        })
+    }
+
+    /// Finds all occurrences of b, searching in windows of interval bits and calling callback with
+    /// the position reached after each window. Stops early if the callback returns False.
+    fn find_all_with_progress(&self, b: &BitRust, bytealigned: bool, max_matches: usize,
+                              callback: &Bound<'_, PyAny>, interval: i64) -> PyResult<Vec<i64>> {
+        if interval <= 0 {
+            return Err(PyValueError::new_err("Progress interval must be positive."));
+        }
+        let mut found: Vec<i64> = Vec::new();
+        let mut start: i64 = 0;
+        let mut next_report = interval;
+        while found.len() < max_matches {
+            // Only positions before next_report are searched, but matches can extend past it.
+            let window_end = std::cmp::min(next_report + b.length - 1, self.length);
+            match self.slice(0, window_end).find(b, start, bytealigned) {
+                Some(x) => {
+                    found.push(start + x);
+                    start += x + 1;
+                }
+                None => {
+                    if window_end == self.length {
+                        break;
+                    }
+                    if callback.call1((next_report,))?.is(&PyBool::new(callback.py(), false).to_owned()) {
+                        break;
+                    }
+                    start = std::cmp::max(start, next_report);
+                    next_report += interval;
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Parses an unpacking token such as 'uint:12' or 'bool' into its name and width in bits. As in
+    /// bitstring the width of a 'bytes' token is in bytes, so it's converted to bits here.
+    fn parse_token(token: &str) -> PyResult<(&str, i64)> {
+        let (name, width) = match token.split_once(':') {
+            Some((name, width)) => {
+                let width: i64 = width.trim().parse()
+                    .map_err(|_| PyValueError::new_err(format!("Invalid width in token '{}'.", token)))?;
+                (name.trim(), width)
+            }
+            None => (token.trim(), if token.trim() == "bool" { 1 } else { -1 }),
+        };
+        match name {
+            "uint" | "int" | "bin" | "hex" | "oct" | "bytes" | "bool" => {}
+            _ => return Err(PyValueError::new_err(format!("Unknown token '{}'.", token))),
+        }
+        let width = if name == "bytes" { width.checked_mul(8).unwrap_or(-1) } else { width };
+        if width <= 0 || (name == "bool" && width != 1) {
+            return Err(PyValueError::new_err(format!("Invalid width in token '{}'.", token)));
+        }
+        Ok((name, width))
+    }
+
+    // I think this works as a Rust version. Keeping this copy for reference.
+    pub fn find_all_rust<'a>(&'a self, b: &'a BitRust, bytealigned: bool) -> impl Iterator<Item = i64> + 'a {
+        // Use the find fn to find all instances of b in self and return as an iterator
+        let mut start: i64 = 0;
+        std::iter::from_fn(move || {
+            let found = self.find(b, start, bytealigned);
+            match found {
+                Some(x) => {
+                    start = start + x + 1;
+                    Some(start - 1)
+                }
+                None => None,
+            }
+        })
+    }
+
+}
+
 #[pymethods]
 impl BitRust {
 
+    /// Create from a str with a '0x', '0b' or '0o' prefix, from bytes, or from a tuple of an
+    /// unsigned int and a length in bits.
+    #[new]
+    pub fn new(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = value.extract::<String>() {
+            return BitRust::from_prefixed_str(&s)
+                .unwrap_or_else(|| Err(PyValueError::new_err("String must start with '0x', '0b' or '0o' to give its format.")));
+        }
+        if let Ok(b) = value.downcast::<PyBytes>() {
+            return Ok(BitRust::from_bytes(b.as_bytes().to_vec()));
+        }
+        if let Ok(t) = value.downcast::<PyTuple>() {
+            if let Ok((uint, length)) = t.extract::<(Bound<'_, PyAny>, i64)>() {
+                return BitRust::from_uint(&uint, length);
+            }
+        }
+        Err(PyValueError::new_err("Can't create a BitRust from this value."))
+    }
+
+    /// Create from a string, working out its format. A '0x', '0b' or '0o' prefix always decides
+    /// the format. Without a prefix a string of only '0' and '1' is taken as binary, otherwise a
+    /// string of hex digits is taken as hex, so for example '10' is two bits but '0x10' is eight.
+    #[pyo3(signature = (s,))]
+    #[staticmethod]
+    pub fn detect_and_parse(s: &str) -> PyResult<Self> {
+        if let Some(result) = BitRust::from_prefixed_str(s) {
+            return result;
+        }
+        if s.is_empty() {
+            return Err(PyValueError::new_err("Can't detect the format of an empty string."));
+        }
+        if s.chars().all(|c| c == '0' || c == '1') {
+            return BitRust::from_bin(s);
+        }
+        if s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return BitRust::from_hex(s);
+        }
+        Err(PyValueError::new_err(format!("Can't detect the format of '{}'.", s)))
+    }
+
+    /// Create from an unsigned int with the given length in bits.
+    #[pyo3(signature = (value, length))]
+    #[staticmethod]
+    pub fn from_uint(value: &Bound<'_, PyAny>, length: i64) -> PyResult<Self> {
+        if length < 0 {
+            return Err(PyValueError::new_err("Length can't be negative."));
+        }
+        let num_bytes = (length + 7) / 8;
+        let bytes = match value.call_method1("to_bytes", (num_bytes, "big")) {
+            Ok(b) => b.extract::<Vec<u8>>()?,
+            Err(_) => return Err(PyValueError::new_err(format!("Value can't be represented as an unsigned int of length {}.", length))),
+        };
+        let bits = BitRust::from_bytes(bytes);
+        let excess = num_bytes * 8 - length;
+        if bits.slice(0, excess).any_set() {
+            return Err(PyValueError::new_err(format!("Value can't be represented as an unsigned int of length {}.", length)));
+        }
+        Ok(bits.slice(excess, bits.length).trim())
+    }
+
     // A stop-gap. We really want to return an iterator of i64.
-    pub fn findall_list(&self, b: &BitRust, bytealigned: bool) -> Vec<i64>  {
-        let pos: Vec<i64> = self.find_all_rust(b, bytealigned).collect();
-        pos
+    /// If max_matches is given then searching stops once that many positions have been found.
+    /// If progress is given it is called with the current bit position each time the search has
+    /// advanced by another progress_interval bits, and the search stops if it returns False.
+    #[pyo3(signature = (b, bytealigned, max_matches=None, progress=None, progress_interval=1 << 20))]
+    pub fn findall_list(&self, b: &BitRust, bytealigned: bool, max_matches: Option<usize>,
+                        progress: Option<&Bound<'_, PyAny>>, progress_interval: i64) -> PyResult<Vec<i64>> {
+        let max_matches = max_matches.unwrap_or(usize::MAX);
+        match progress {
+            Some(callback) => self.find_all_with_progress(b, bytealigned, max_matches, callback, progress_interval),
+            None => Ok(self.find_all_rust(b, bytealigned).take(max_matches).collect()),
+        }
+    }
+
+    /// Returns an iterator over the positions of b. Each position is only searched for when it's
+    /// requested, so stopping early avoids scanning the rest of the data.
+    pub fn ifind_all(&self, b: &BitRust, bytealigned: bool) -> FindAllIterator {
+        FindAllIterator {
+            bits: self.clone(),
+            pattern: b.clone(),
+            bytealigned,
+            start: 0,
+        }
     }
 
     pub fn __len__(&self) -> usize {
         self.length as usize
     }
 
-    pub fn __eq__(&self, rhs: &BitRust) -> bool {
-        self == rhs
+    /// Equality with another BitRust, with bytes (if a whole number of bytes long) or with a str
+    /// that has a '0x', '0b' or '0o' prefix. Other comparisons are not supported.
+    pub fn __richcmp__(&self, other: &Bound<'_, PyAny>, op: CompareOp) -> PyResult<PyObject> {
+        let py = other.py();
+        let equal = if let Ok(b) = other.downcast::<BitRust>() {
+            *self == *b.borrow()
+        } else if let Ok(b) = other.downcast::<PyBytes>() {
+            self.length % 8 == 0 && self.to_bytes() == b.as_bytes()
+        } else if other.downcast::<PyString>().is_ok() {
+            match BitRust::new(other) {
+                Ok(b) => *self == b,
+                Err(_) => false,
+            }
+        } else {
+            return Ok(py.NotImplemented());
+        };
+        match op {
+            CompareOp::Eq => Ok(PyBool::new(py, equal).to_owned().into_any().unbind()),
+            CompareOp::Ne => Ok(PyBool::new(py, !equal).to_owned().into_any().unbind()),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    /// Returns a bool for an integer index, or a new BitRust for a slice. Slices can have any step.
+    /// A slice with a step of 1 shares storage with self, while other steps copy the bits.
+    /// Out of range integer indices raise IndexError, while slices are clamped to the length.
+    /// A list or tuple of indices gives a new BitRust gathering those bits.
+    pub fn __getitem__(&self, key: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let py = key.py();
+        if let Ok(slice) = key.downcast::<PySlice>() {
+            let indices = slice.indices(self.length as isize)?;
+            let bits = self.getslice_with_step(indices.start as i64, indices.step as i64, indices.slicelength as i64);
+            return Ok(Py::new(py, bits)?.into_any());
+        }
+        if key.downcast::<PyList>().is_ok() || key.downcast::<PyTuple>().is_ok() {
+            let indices: Vec<i64> = key.extract()?;
+            return Ok(Py::new(py, self.gather(&indices)?)?.into_any());
+        }
+        let index: i64 = match key.extract() {
+            Ok(index) => index,
+            // An int too large for i64 is out of range, as for a Python list.
+            Err(_) if key.downcast::<PyInt>().is_ok() => return Err(PyIndexError::new_err("Out of range.")),
+            Err(e) => return Err(e),
+        };
+        Ok(PyBool::new(py, self.getindex(index)?).to_owned().into_any().unbind())
+    }
+
+    pub fn __iter__(&self) -> BitRustIterator {
+        BitRustIterator {
+            bits: self.clone(),
+            index: 0,
+        }
+    }
+
+    /// Returns true if the shorter value, when padded with zero bits on the right to the length of
+    /// the longer, is equal to the longer value.
+    pub fn equals_padded(&self, other: &BitRust) -> bool {
+        let (short, long) = if self.length <= other.length { (self, other) } else { (other, self) };
+        long.slice(0, short.length) == *short && !long.slice(short.length, long.length).any_set()
+    }
+
+    /// Returns true if b is found anywhere. A fast byte search is tried first when possible.
+    pub fn __contains__(&self, b: &BitRust) -> bool {
+        if self.can_search_bytes(b) && self.find_bytes(b, 0).is_some() {
+            return true;
+        }
+        self.find(b, 0, false).is_some()
+    }
+
+    /// Returns the approximate memory used in bytes. Slices share their buffer, so the whole
+    /// buffer is counted rather than just the bytes covered by the length.
+    pub fn __sizeof__(&self) -> usize {
+        std::mem::size_of::<BitRust>() + self.data.capacity()
+    }
+
+    /// Allows the class to be subscripted in type hints, for example BitRust[int].
+    #[classmethod]
+    pub fn __class_getitem__<'py>(cls: &Bound<'py, PyType>, item: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+        cls.py().import("types")?.getattr("GenericAlias")?.call1((cls, item))
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    /// Sets the maximum number of bits shown by __repr__. The default is 100. The limit can't be
+    /// more than the largest possible length.
+    #[staticmethod]
+    pub fn set_repr_limit(n: usize) -> PyResult<()> {
+        if n > i64::MAX as usize {
+            return Err(PyValueError::new_err("Limit is too large."));
+        }
+        REPR_LIMIT.store(n, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Shallow copy for copy.copy. The copy shares storage with the original. This is safe because
+    /// the shared bytes are never written to: set_mutable_slice builds a new BitRust and replaces
+    /// the whole struct, leaving any other holder of the Arc untouched.
+    pub fn __copy__(&self) -> Self {
+        self.clone()
+    }
+
+    /// Support for copy and pickle. Only the bytes covered by the value are stored.
+    pub fn __reduce__<'py>(slf: &Bound<'py, Self>) -> PyResult<(Bound<'py, PyAny>, State)> {
+        let bits = slf.borrow();
+        let state = (bits.active_data(), bits.offset % 8, bits.length);
+        Ok((slf.get_type().getattr("from_state")?, state))
     }
 
     #[pyo3(signature = (length,))]
@@ -265,6 +1136,7 @@ impl BitRust {
             data: Arc::new(vec![0; ((length + 7) / 8) as usize]),
             offset: 0,
             length,
+            cache: ReprCache::default(),
         }
     }
 
@@ -275,6 +1147,7 @@ impl BitRust {
             data: Arc::new(vec![0xff; ((length + 7) / 8) as usize]),
             offset: 0,
             length,
+            cache: ReprCache::default(),
         }
     }
 
@@ -286,7 +1159,43 @@ impl BitRust {
             data: Arc::new(data),
             offset: 0,
             length: bitlength,
+            cache: ReprCache::default(),
+        }
+    }
+
+    /// Create from all of the bytes returned by a file-like object's read method.
+    #[pyo3(signature = (file_obj,))]
+    #[staticmethod]
+    pub fn read_from(file_obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let data = file_obj.call_method0("read")?;
+        match data.downcast::<PyBytes>() {
+            Ok(b) => Ok(BitRust::from_bytes(b.as_bytes().to_vec())),
+            Err(_) => Err(PyTypeError::new_err("The read method must return bytes.")),
+        }
+    }
+
+    /// Create from the raw data, bit offset and bit length, as returned by to_bitstring_state.
+    #[pyo3(signature = (data, offset, length))]
+    #[staticmethod]
+    pub fn from_state(data: Vec<u8>, offset: i64, length: i64) -> PyResult<Self> {
+        let bits = BitRust {
+            data: Arc::new(data),
+            offset,
+            length,
+            cache: ReprCache::default(),
+        };
+        bits.validate()?;
+        Ok(bits)
+    }
+
+    /// Create from bytes. If lsb_first is true then the bit order within each byte is reversed.
+    #[pyo3(signature = (data, lsb_first))]
+    #[staticmethod]
+    pub fn from_bytes_bitorder(data: Vec<u8>, lsb_first: bool) -> Self {
+        if lsb_first {
+            return BitRust::from_bytes(data.iter().map(|b| b.reverse_bits()).collect());
         }
+        BitRust::from_bytes(data)
     }
 
     #[pyo3(signature = (data, offset))]
@@ -298,56 +1207,123 @@ impl BitRust {
             data: Arc::new(data),
             offset,
             length: bitlength,
+            cache: ReprCache::default(),
         }
     }
 
-    #[pyo3(signature = (binary_string,))]
+    /// Create by copying a contiguous numpy uint8 array, or any other buffer of unsigned bytes.
+    /// This makes a copy: a BitRust owns its bytes, so it can't borrow the array's memory, and
+    /// later changes to the array are not seen. The only saving is not converting to bytes first.
+    #[pyo3(signature = (arr,))]
     #[staticmethod]
-    pub fn from_bin(binary_string: &str) -> PyResult<Self> {
-        let mut data: Vec<u8> = Vec::new();
-        let mut byte: u8 = 0;
-        for chunk in binary_string.as_bytes().chunks(8) {
-            for (i, &c) in chunk.iter().enumerate() {
-                if c == b'1' {
-                    byte |= 1 << (7 - i);
-                } else if c != b'0' {
-                    return Err(PyValueError::new_err("Invalid character"));
-                }
+    pub fn from_numpy(arr: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let buffer = match PyBuffer::<u8>::get(arr) {
+            Ok(b) => b,
+            Err(_) => return Err(PyValueError::new_err("Expected an array with dtype uint8.")),
+        };
+        if !buffer.is_c_contiguous() {
+            return Err(PyValueError::new_err("Array is not contiguous."));
+        }
+        Ok(BitRust::from_bytes(buffer.to_vec(arr.py())?))
+    }
+
+    /// Create from a one-dimensional numpy bool array, with one bit per element.
+    #[pyo3(signature = (arr,))]
+    #[staticmethod]
+    pub fn from_bool_array(arr: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if arr.getattr("ndim")?.extract::<usize>()? != 1 {
+            return Err(PyValueError::new_err("Expected a one-dimensional array."));
+        }
+        if arr.getattr("dtype")?.getattr("kind")?.extract::<String>()? != "b" {
+            return Err(PyValueError::new_err("Expected an array with dtype bool."));
+        }
+        let bytes = arr.call_method0("tobytes")?;
+        let bools = bytes.downcast::<PyBytes>()?.as_bytes();
+        Ok(BitRust::from_bools(bools.iter().map(|&b| b != 0)))
+    }
+
+    /// Create from a string of binary digits, given as a str or ASCII bytes. The characters used
+    /// for the zero and one bits can be changed, for example to '.' and '*'. If length is given
+    /// then the number of bits parsed must equal it.
+    #[pyo3(name = "from_bin", signature = (binary_string, zero='0', one='1', length=None))]
+    #[staticmethod]
+    pub fn from_bin_py(binary_string: StrOrBytes, zero: char, one: char, length: Option<i64>) -> PyResult<Self> {
+        let bits = BitRust::from_bin_with_chars(&binary_string.0, zero, one)?;
+        if let Some(length) = length {
+            if bits.length != length {
+                return Err(PyValueError::new_err(format!(
+                    "Expected {} bits but the binary string has {}.", length, bits.length)));
             }
-            data.push(byte);
-            byte = 0;
         }
-        Ok(BitRust {
-            data: Arc::new(data),
-            offset: 0,
-            length: binary_string.len() as i64,
-        })
+        Ok(bits)
     }
 
+    /// Create from a string of hex digits, given as a str or ASCII bytes.
+    #[pyo3(name = "from_hex", signature = (hex,))]
+    #[staticmethod]
+    pub fn from_hex_py(hex: StrOrBytes) -> PyResult<Self> {
+        BitRust::from_hex(&hex.0)
+    }
+
+    /// Lenient parsing of a hex string. Parsing stops at the first invalid character rather than
+    /// raising an error, and the number of characters used is returned along with the result.
     #[pyo3(signature = (hex,))]
     #[staticmethod]
-    pub fn from_hex(hex: &str) -> PyResult<Self> {
-        let mut new_hex = hex.to_string();
-        let is_odd_length: bool = hex.len() % 2 != 0;
-        if is_odd_length {
-            new_hex.push('0');
-        }
-        let data = match hex::decode(new_hex) {
-            Ok(d) => d,
-            Err(_) => return Err(PyValueError::new_err("Invalid character")),
-        };
+    pub fn from_hex_lenient(hex: &str) -> (Self, usize) {
+        let consumed = hex.chars().take_while(|c| c.is_ascii_hexdigit()).count();
+        (BitRust::from_hex(&hex[..consumed]).unwrap(), consumed)
+    }
+
+    /// Joins any iterable of BitRust, such as a list or a generator. A list or tuple is already in
+    /// memory so is joined in one go, which allows the output to be sized up front. Any other
+    /// iterable is consumed one element at a time and appended to the output buffer, so it's never
+    /// materialized.
+    #[pyo3(signature = (bits_vec,))]
+    #[staticmethod]
+    pub fn join(bits_vec: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if bits_vec.is_instance_of::<PyList>() || bits_vec.is_instance_of::<PyTuple>() {
+            let refs: Vec<PyRef<BitRust>> = bits_vec.extract()?;
+            let my_vec: Vec<&BitRust> = refs.iter().map(|x| &**x).collect();
+            return Ok(BitRust::join_internal(&my_vec));
+        }
+        let mut data: Vec<u8> = Vec::new();
+        let mut length: i64 = 0;
+        for item in bits_vec.try_iter()? {
+            let item = item?;
+            let bits = item.downcast::<BitRust>()?.borrow();
+            BitRust::append_to(&mut data, length, &bits);
+            length += bits.length;
+        }
         Ok(BitRust {
             data: Arc::new(data),
             offset: 0,
-            length: hex.len() as i64 * 4,
+            length,
+            cache: ReprCache::default(),
         })
     }
 
+    /// Returns the bit-wise majority vote of equal length values. Ties give a zero bit.
     #[pyo3(signature = (bits_vec,))]
     #[staticmethod]
-    pub fn join(bits_vec: Vec<PyRef<BitRust>>) -> Self {
+    pub fn majority(bits_vec: Vec<PyRef<BitRust>>) -> PyResult<Self> {
         let my_vec: Vec<&BitRust> = bits_vec.iter().map(|x| &**x).collect();
-        return BitRust::join_internal(&my_vec);
+        BitRust::majority_internal(&my_vec)
+    }
+
+    /// Create from an integer as binary-coded decimal, with one decimal digit per nibble. The
+    /// length must be a multiple of 4 bits and have room for every digit.
+    #[pyo3(signature = (value, length))]
+    #[staticmethod]
+    pub fn from_bcd(value: u64, length: i64) -> PyResult<Self> {
+        if length < 0 || length % 4 != 0 {
+            return Err(PyValueError::new_err("Length must be a multiple of 4 bits."));
+        }
+        let digits = value.to_string();
+        if digits.len() as i64 * 4 > length {
+            return Err(PyValueError::new_err(format!("Value needs more than {} bits as BCD.", length)));
+        }
+        let padded = format!("{:0>width$}", digits, width = (length / 4) as usize);
+        BitRust::from_hex(&padded)
     }
 
     #[pyo3(signature = (oct,))]
@@ -386,6 +1362,142 @@ impl BitRust {
         bytes
     }
 
+    /// Convert to bytes without any padding. The length must be a whole number of bytes.
+    pub fn to_bytes_exact(&self) -> PyResult<Vec<u8>> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        Ok(self.to_bytes())
+    }
+
+    /// Convert to a numpy uint8 array. The length must be a whole number of bytes. The array is
+    /// writable and has its own copy of the bytes, so it never shares memory with the BitRust.
+    pub fn to_numpy(&self, py: Python<'_>) -> PyResult<PyObject> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        // numpy wraps a bytearray as a writable array without copying it again.
+        let bytes = PyByteArray::new(py, &self.to_bytes());
+        let arr = py.import("numpy")?.call_method1("frombuffer", (bytes, "uint8"))?;
+        Ok(arr.unbind())
+    }
+
+    /// Convert to a writable numpy bool array with one element per bit.
+    pub fn to_bool_array(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let bools: Vec<u8> = (0..self.length).map(|i| self.get_bit(i) as u8).collect();
+        let bytes = PyByteArray::new(py, &bools);
+        let arr = py.import("numpy")?.call_method1("frombuffer", (bytes, "bool"))?;
+        Ok(arr.unbind())
+    }
+
+    /// Convert to bytes, with the bit order within each byte reversed if lsb_first is true.
+    /// The length must be a whole number of bytes.
+    pub fn to_bytes_bitorder(&self, lsb_first: bool) -> PyResult<Vec<u8>> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        let bytes = self.to_bytes();
+        if lsb_first {
+            return Ok(bytes.iter().map(|b| b.reverse_bits()).collect());
+        }
+        Ok(bytes)
+    }
+
+    /// Returns the bytes (with zero padding at the end) and the length in bits. Equal values give
+    /// equal tuples whatever their internal offsets, so this can be used to build hashes or keys.
+    pub fn as_tuple(&self) -> (Vec<u8>, i64) {
+        (self.to_bytes(), self.length)
+    }
+
+    /// Returns an iterator over the bytes in chunks of chunk_bytes. The final chunk may be shorter.
+    /// The length must be a whole number of bytes.
+    pub fn byte_chunks(&self, chunk_bytes: usize) -> PyResult<BytesChunkIterator> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        if chunk_bytes == 0 {
+            return Err(PyValueError::new_err("Chunk size must be at least 1 byte."));
+        }
+        let bits = if self.offset % 8 == 0 { self.clone() } else { self.copy_with_new_offset(0) };
+        // A chunk never needs to be longer than the whole value, and clamping keeps the positions
+        // from overflowing.
+        Ok(BytesChunkIterator {
+            bits,
+            chunk_bytes: std::cmp::min(chunk_bytes, std::cmp::max(self.length / 8, 1) as usize),
+            pos: 0,
+        })
+    }
+
+    /// Decodes consecutive fields into a dict. Each spec entry is a (name, token) pair, where the
+    /// token is 'uint:N', 'int:N', 'bin:N', 'hex:N', 'oct:N', 'bytes:N' or 'bool'. Widths are in
+    /// bits except for 'bytes:N', which is N bytes as in bitstring. Any bits after the last field
+    /// are ignored.
+    pub fn unpack_to_dict(&self, py: Python<'_>, spec: Vec<(String, String)>) -> PyResult<PyObject> {
+        let tokens = spec.iter()
+            .map(|(_, token)| BitRust::parse_token(token))
+            .collect::<PyResult<Vec<_>>>()?;
+        let total = tokens.iter().try_fold(0i64, |total, (_, width)| total.checked_add(*width));
+        if !matches!(total, Some(total) if total <= self.length) {
+            return Err(PyValueError::new_err("The spec is longer than the data."));
+        }
+        let dict = PyDict::new(py);
+        let mut pos: i64 = 0;
+        for ((name, _), (kind, width)) in spec.iter().zip(tokens) {
+            let field = self.slice(pos, pos + width);
+            match kind {
+                "uint" | "int" => {
+                    let kwargs = PyDict::new(py);
+                    kwargs.set_item("signed", kind == "int")?;
+                    let data = PyBytes::new(py, &field.to_int_byte_data(kind == "int"));
+                    let value = py.get_type::<PyInt>().call_method("from_bytes", (data, "big"), Some(&kwargs))?;
+                    dict.set_item(name, value)?;
+                }
+                "bin" => dict.set_item(name, field.compute_bin())?,
+                "hex" => dict.set_item(name, field.to_hex()?)?,
+                "oct" => dict.set_item(name, field.to_oct()?)?,
+                "bytes" => dict.set_item(name, PyBytes::new(py, &field.to_bytes_exact()?))?,
+                _ => dict.set_item(name, field.get_bit(0))?,
+            }
+            pos += width;
+        }
+        Ok(dict.into_any().unbind())
+    }
+
+    /// Returns an iterator over the hex representation in strings of chars_per_chunk characters.
+    /// The final chunk may be shorter. The length must be a multiple of 4 bits.
+    pub fn hex_chunks(&self, chars_per_chunk: usize) -> PyResult<HexChunkIterator> {
+        if self.length % 4 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 4 bits long."));
+        }
+        if chars_per_chunk == 0 {
+            return Err(PyValueError::new_err("Chunk size must be positive."));
+        }
+        // A chunk never needs to be longer than the whole value, and clamping keeps the size in
+        // bits from overflowing.
+        Ok(HexChunkIterator {
+            bits: self.clone(),
+            chars_per_chunk: std::cmp::min(chars_per_chunk, std::cmp::max(self.length / 4, 1) as usize),
+            pos: 0,
+        })
+    }
+
+    /// Returns the raw data, bit offset and bit length exactly as stored. The whole of a shared
+    /// buffer is included, so this can be large for a short slice.
+    pub fn to_bitstring_state(&self) -> (Vec<u8>, i64, i64) {
+        (self.data.to_vec(), self.offset, self.length)
+    }
+
+    /// Writes the bytes to a file-like object using its write method. The length must be a whole
+    /// number of bytes.
+    pub fn write_to(&self, file_obj: &Bound<'_, PyAny>) -> PyResult<()> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        let bytes = PyBytes::new(file_obj.py(), &self.to_bytes());
+        file_obj.call_method1("write", (bytes,))?;
+        Ok(())
+    }
+
     // Just the byte data without any shifting or padding.
     pub fn to_byte_data_with_offset(&self) -> (Vec<u8>, i64) {
         (self.active_data(), self.offset % 8)
@@ -416,46 +1528,79 @@ impl BitRust {
         if self.length % 4 != 0 {
             return Err(PyValueError::new_err("Not a multiple of 4 bits long."));
         }
-        let bit_offset = self.offset % 8;
-        let nibble_offset_data: &Vec<u8> = if bit_offset == 0 || bit_offset == 4 {
-            &self.active_data()
-        } else {
-            &self.copy_with_new_offset(0).data
-        };
-        let x = nibble_offset_data.iter()
-            .map(|byte| format!("{:02x}", byte))
-            .fold(String::new(), |mut acc, hex| {
-                acc.push_str(&hex);
-                acc
-            });
-        if bit_offset == 4 {
-            if self.length % 8 == 0 {
-                return Ok(x[1..x.len()-1].to_string());
-            }
-            return Ok(x[1..].to_string());
+        Ok(self.cache.hex.get_or_init(|| self.compute_hex()).clone())
+    }
+
+    pub fn to_bin(&self) -> String {
+        self.cache.bin.get_or_init(|| self.compute_bin()).clone()
+    }
+
+    /// Returns the binary string of just the first n bits, without building the whole string.
+    pub fn bin_prefix(&self, n: i64) -> PyResult<String> {
+        if n < 0 || n > self.length {
+            return Err(PyIndexError::new_err("Out of range."));
         }
-        if self.length % 8 == 0 {
-            return Ok(x);
+        Ok((0..n).map(|i| if self.get_bit(i) { '1' } else { '0' }).collect())
+    }
+
+    /// Binary string using the given characters for the zero and one bits.
+    pub fn to_bin_mapped(&self, zero: char, one: char) -> String {
+        self.compute_bin().chars()
+            .map(|c| if c == '1' { one } else { zero })
+            .collect()
+    }
+
+    /// Pretty-printed multi-line string for debugging. Each row shows the bit position of its
+    /// start, then the hex and binary of up to width bytes. The width can be at most 4096 bytes, as
+    /// each row is padded to its full width.
+    pub fn pp(&self, width: usize) -> PyResult<String> {
+        if width == 0 || width > 4096 {
+            return Err(PyValueError::new_err("Width must be between 1 and 4096 bytes."));
         }
-        debug_assert_eq!(self.length % 8, 4);
-        Ok(x[..x.len()-1].to_string())
+        let row_bits = width as i64 * 8;
+        let mut rows: Vec<String> = Vec::new();
+        let mut start: i64 = 0;
+        while start < self.length {
+            let end = std::cmp::min(start + row_bits, self.length);
+            let mut hex_parts: Vec<String> = Vec::new();
+            let mut bin_parts: Vec<String> = Vec::new();
+            let mut pos = start;
+            while pos < end {
+                let byte = self.slice(pos, std::cmp::min(pos + 8, end));
+                hex_parts.push(byte.to_hex().unwrap_or_default());
+                bin_parts.push(byte.compute_bin());
+                pos += 8;
+            }
+            rows.push(format!("{:>8}: {:<hex_width$}  {}", start, hex_parts.join(" "), bin_parts.join(" "),
+                              hex_width = width * 3 - 1));
+            start = end;
+        }
+        Ok(rows.join("\n"))
     }
 
-    pub fn to_bin(&self) -> String {
-        let x = self.data.iter()
-            .map(|byte| format!("{:08b}", byte))
-            .fold(String::new(), |mut bin_str, bin| {
-                bin_str.push_str(&bin);
-                bin_str
-            });
-        x[self.offset as usize..(self.offset + self.length) as usize].to_string()
+    /// Interprets each nibble as a decimal digit. The length must be a multiple of 4 bits.
+    pub fn to_bcd(&self) -> PyResult<u64> {
+        if self.length % 4 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 4 bits long."));
+        }
+        let mut value: u64 = 0;
+        for start in (0..self.length).step_by(4) {
+            let digit = self.slice(start, start + 4).to_u64();
+            if digit > 9 {
+                return Err(PyValueError::new_err("Nibble is not a decimal digit."));
+            }
+            value = value.checked_mul(10)
+                .and_then(|v| v.checked_add(digit))
+                .ok_or_else(|| PyValueError::new_err("Value is too large for 64 bits."))?;
+        }
+        Ok(value)
     }
 
     pub fn to_oct(&self) -> PyResult<String> {
         if self.length % 3 != 0 {
             return Err(PyValueError::new_err("Not a multiple of 3 bits long."));
         }
-        let bin_str = self.to_bin();
+        let bin_str = self.compute_bin();
         let mut oct_str: String = String::new();
 
         for chunk in bin_str.as_bytes().chunks(3) {
@@ -466,6 +1611,15 @@ impl BitRust {
         Ok(oct_str)
     }
 
+    /// Returns a string in the style of the bitstring library's repr, for example "Bits('0x0a1')".
+    /// Hex is used if the length is a multiple of 4 bits, otherwise binary.
+    pub fn bitstring_repr(&self) -> String {
+        if self.length % 4 == 0 {
+            return format!("Bits('0x{}')", self.to_hex().unwrap());
+        }
+        format!("Bits('0b{}')", self.to_bin())
+    }
+
     pub fn __and__(&self, other: &BitRust) -> PyResult<BitRust> {
         match self.bitwise_op(other, |a, b| a & b) {
             Ok(b) => Ok(b),
@@ -484,16 +1638,134 @@ impl BitRust {
             Err(_) => Err(PyValueError::new_err("Lengths do not match.")),
         }
     }
-    
-    pub fn find(&self, b: &BitRust, start: i64, bytealigned: bool) -> Option<i64> {
-        if b.length > self.length - start {
-            return None;
+
+    /// Returns the sorted positions where two BitRust of equal length differ.
+    pub fn diff(&self, other: &BitRust) -> PyResult<Vec<i64>> {
+        if self.length != other.length {
+            return Err(PyValueError::new_err("Lengths do not match."));
         }
-        let step = if bytealigned { 8 } else { 1 };
-        let mut pos = if bytealigned { (start + 7) / 8 * 8 } else { start };
-        while pos <= self.length - b.length {
-            if self.slice(pos, pos + b.length) == *b {
-                return Some(pos - start);
+        let x = self.__xor__(other)?;
+        let mut positions: Vec<i64> = Vec::new();
+        for (i, &byte) in x.data.iter().enumerate() {
+            if byte == 0 {
+                continue;
+            }
+            for j in 0..8 {
+                let pos = i as i64 * 8 + j - x.offset;
+                if byte & (128 >> j) != 0 && pos >= 0 && pos < x.length {
+                    positions.push(pos);
+                }
+            }
+        }
+        Ok(positions)
+    }
+
+    /// Bitwise AND with an integer mask, with the mask aligned to the right-most bits.
+    pub fn and_uint(&self, mask: u64) -> PyResult<Self> {
+        self.uint_op(mask, |a, b| a & b)
+    }
+
+    /// Bitwise OR with an integer mask, with the mask aligned to the right-most bits.
+    pub fn or_uint(&self, mask: u64) -> PyResult<Self> {
+        self.uint_op(mask, |a, b| a | b)
+    }
+
+    /// Bitwise XOR with an integer mask, with the mask aligned to the right-most bits.
+    pub fn xor_uint(&self, mask: u64) -> PyResult<Self> {
+        self.uint_op(mask, |a, b| a ^ b)
+    }
+
+    /// Combines two equal length values with any of the 16 binary boolean functions. Bit 2a + b of
+    /// truth_table gives the output for input bits a and b, so 0b1000 is AND and 0b0110 is XOR.
+    pub fn combine(&self, other: &BitRust, truth_table: u8) -> PyResult<BitRust> {
+        if truth_table > 15 {
+            return Err(PyValueError::new_err("Truth table must be between 0 and 15."));
+        }
+        if self.length != other.length {
+            return Err(PyValueError::new_err("Lengths do not match."));
+        }
+        let op = |x: u8, y: u8| {
+            let mut result: u8 = 0;
+            for (i, (a, b)) in [(!x, !y), (!x, y), (x, !y), (x, y)].iter().enumerate() {
+                if truth_table & (1 << i) != 0 {
+                    result |= a & b;
+                }
+            }
+            result
+        };
+        Ok(self.bitwise_op(other, op).unwrap())
+    }
+
+    /// Returns a mask with a 1 wherever the two equal length values agree.
+    pub fn match_mask(&self, other: &BitRust) -> PyResult<BitRust> {
+        if self.length != other.length {
+            return Err(PyValueError::new_err("Lengths do not match."));
+        }
+        self.__xor__(other).map(|x| x.invert(None))
+    }
+
+    /// Multiplies by a generator matrix over GF(2). Each output bit is the parity of the AND of self
+    /// with the corresponding row, and all rows must have the same length as self.
+    pub fn matmul(&self, generator_rows: Vec<PyRef<BitRust>>) -> PyResult<BitRust> {
+        let rows: Vec<&BitRust> = generator_rows.iter().map(|x| &**x).collect();
+        self.parity_products(&rows)
+    }
+
+    /// Returns the syndrome of a received codeword for a parity-check matrix. Each output bit is the
+    /// parity of the AND of self with the corresponding row. A zero syndrome means no error was
+    /// detected.
+    pub fn syndrome(&self, parity_rows: Vec<PyRef<BitRust>>) -> PyResult<BitRust> {
+        let rows: Vec<&BitRust> = parity_rows.iter().map(|x| &**x).collect();
+        self.parity_products(&rows)
+    }
+
+    /// Repeatedly folds in half, XORing the second half onto the first, until the length is target.
+    /// If the length is odd the second half is padded with a zero bit, and the final fold is
+    /// shortened so that exactly target bits remain.
+    pub fn fold_to_length(&self, target: i64) -> PyResult<BitRust> {
+        if target <= 0 || target > self.length {
+            return Err(PyValueError::new_err("Target must be between 1 and the length."));
+        }
+        let mut folded = self.clone();
+        while folded.length > target {
+            let half = std::cmp::max((folded.length + 1) / 2, target);
+            let first = folded.slice(0, half);
+            let second = BitRust::join_internal(&[
+                &folded.slice(half, folded.length),
+                &BitRust::from_zeros(2 * half - folded.length),
+            ]);
+            folded = first.__xor__(&second)?;
+        }
+        Ok(folded)
+    }
+
+    /// XOR with the key repeated as many times as needed to match the length.
+    pub fn xor_repeating(&self, key: &BitRust) -> PyResult<BitRust> {
+        if key.length == 0 {
+            return Err(PyValueError::new_err("Key can't be empty."));
+        }
+        let repeats = (self.length + key.length - 1) / key.length;
+        let tiled = BitRust::join_internal(&vec![key; repeats as usize]).slice(0, self.length);
+        self.__xor__(&tiled)
+    }
+
+    /// Returns the position of the first occurrence of b, relative to start. An empty b is found
+    /// immediately, so gives Some(0) whenever start is within range.
+    pub fn find(&self, b: &BitRust, start: i64, bytealigned: bool) -> Option<i64> {
+        if b.length > self.length - start {
+            return None;
+        }
+        if b.length == 0 {
+            return Some(0);
+        }
+        let step = if bytealigned { 8 } else { 1 };
+        let mut pos = if bytealigned { (start + 7) / 8 * 8 } else { start };
+        if bytealigned && self.can_search_bytes(b) {
+            return self.find_bytes(b, pos).map(|x| x - start);
+        }
+        while pos <= self.length - b.length {
+            if self.slice(pos, pos + b.length) == *b {
+                return Some(pos - start);
             }
             pos += step;
         }
@@ -518,6 +1790,36 @@ impl BitRust {
         None
     }
 
+    /// Returns whether pattern occurs starting at the bit position pos.
+    pub fn matches_at(&self, pos: i64, pattern: &BitRust) -> PyResult<bool> {
+        if pos < 0 || pos > self.length - pattern.length {
+            return Err(PyIndexError::new_err("Pattern extends beyond the end."));
+        }
+        Ok(self.slice(pos, pos + pattern.length) == *pattern)
+    }
+
+    /// Returns whether other occurs starting at the bit position start. Unlike matches_at, a
+    /// position where other would run past the end gives false rather than an error.
+    pub fn subrange_equals(&self, start: i64, other: &BitRust) -> bool {
+        start >= 0 && start <= self.length - other.length
+            && self.slice(start, start + other.length) == *other
+    }
+
+    /// Returns the number of times b is found, without building a list of positions.
+    pub fn count_occurrences(&self, b: &BitRust, bytealigned: bool, allow_overlap: bool) -> PyResult<i64> {
+        if b.length == 0 {
+            return Err(PyValueError::new_err("Can't count occurrences of an empty BitRust."));
+        }
+        let step = if allow_overlap { 1 } else { b.length };
+        let mut count: i64 = 0;
+        let mut start: i64 = 0;
+        while let Some(pos) = self.find(b, start, bytealigned) {
+            count += 1;
+            start += pos + step;
+        }
+        Ok(count)
+    }
+
     pub fn count(&self) -> i64 {
         if self.length == 0 {
             return 0;
@@ -526,32 +1828,425 @@ impl BitRust {
         let padding = if (self.length + offset) % 8 == 0 { 0 } else { 8 - (self.length + offset) % 8 };
         // Case where there's only one byte of used data.
         if self.start_byte() + 1 == self.end_byte() {
-            return ((self.data[self.start_byte()] << offset) >> (offset + padding)).count_ones() as i64;
+            return byte_count_ones((self.data[self.start_byte()] << offset) >> (offset + padding)) as i64;
         }
+        #[cfg(feature = "simd")]
+        let mut c = crate::simd::count_ones(&self.data[self.start_byte()..self.end_byte()]) as i64;
+        #[cfg(not(feature = "simd"))]
         let mut c = hamming::weight(&self.data[self.start_byte()..self.end_byte()]) as i64;
         // Subtract any bits in the offset or padding.
         if offset != 0 {
-            c -= (self.data[self.start_byte()] >> (8 - offset)).count_ones() as i64;
+            c -= byte_count_ones(self.data[self.start_byte()] >> (8 - offset)) as i64;
         }
         if padding != 0 {
-            c -= (self.data[self.end_byte() - 1] << (8 -padding)).count_ones() as i64;
+            c -= byte_count_ones(self.data[self.end_byte() - 1] << (8 -padding)) as i64;
         }
         c
     }
 
+    /// Returns the number of set bits. The same as count, named to match int.bit_count.
+    pub fn bit_count(&self) -> i64 {
+        self.count()
+    }
+
+    /// Returns the number of zero bits and the number of one bits.
+    pub fn tally(&self) -> (i64, i64) {
+        let ones = self.count();
+        (self.length - ones, ones)
+    }
+
+    /// Returns the number of set bits at positions where mask is also set. The lengths must match.
+    pub fn count_ones_masked(&self, mask: &BitRust) -> PyResult<i64> {
+        if self.length != mask.length {
+            return Err(PyValueError::new_err("Lengths do not match."));
+        }
+        if self.length == 0 {
+            return Ok(0);
+        }
+        let offset = self.offset % 8;
+        let padding = (8 - (self.length + offset) % 8) % 8;
+        let aligned = mask.offset % 8 == offset;
+        let bytes = &self.data[self.start_byte()..self.end_byte()];
+        let last = bytes.len() - 1;
+        let mut c = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            let mask_byte = if aligned {
+                mask.data[mask.start_byte() + i]
+            } else {
+                mask.byte_at(mask.offset - offset + 8 * i as i64)
+            };
+            let mut both = byte & mask_byte;
+            // Ignore the bits in the offset and padding.
+            if i == 0 {
+                both &= 0xff >> offset;
+            }
+            if i == last {
+                both &= 0xff << padding;
+            }
+            c += byte_count_ones(both) as i64;
+        }
+        Ok(c)
+    }
+
+    /// Returns the 16-bit one's complement checksum used by IP, TCP and UDP. The data must be a
+    /// whole number of bytes, and if there are an odd number of bytes it is padded with a zero
+    /// byte as described in RFC 1071.
+    pub fn inet_checksum(&self) -> PyResult<u16> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        let bytes = self.to_bytes();
+        let mut sum: u32 = 0;
+        for word in bytes.chunks(2) {
+            let low = if word.len() == 2 { word[1] } else { 0 };
+            sum += u16::from_be_bytes([word[0], low]) as u32;
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        Ok(!(sum as u16))
+    }
+
+    /// Returns the Fletcher-16 checksum of the bytes. The data must be a whole number of bytes.
+    pub fn fletcher16(&self) -> PyResult<u16> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        let mut sum1: u16 = 0;
+        let mut sum2: u16 = 0;
+        for byte in self.to_bytes() {
+            sum1 = (sum1 + byte as u16) % 255;
+            sum2 = (sum2 + sum1) % 255;
+        }
+        Ok((sum2 << 8) | sum1)
+    }
+
+    /// Returns the concatenation of self and other together with its length.
+    pub fn extended(&self, other: &BitRust) -> (Self, i64) {
+        let result = BitRust::join_internal(&[self, other]);
+        let length = result.length;
+        (result, length)
+    }
+
+    /// Returns the number of set bits in each byte. The data must be a whole number of bytes.
+    pub fn popcount_per_byte(&self) -> PyResult<Vec<u8>> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        Ok(self.to_bytes().iter().map(|&b| byte_count_ones(b) as u8).collect())
+    }
+
+    /// Returns the number of set bits in each consecutive block of window bits. The final block is
+    /// shorter if the length isn't a multiple of window.
+    pub fn windowed_popcount(&self, window: i64) -> PyResult<Vec<i64>> {
+        if window <= 0 {
+            return Err(PyValueError::new_err("Window size must be positive."));
+        }
+        Ok((0..self.length)
+            .step_by(window as usize)
+            .map(|start| self.slice(start, std::cmp::min(start + window, self.length)).count())
+            .collect())
+    }
+
+    /// Returns the number of times adjacent bits differ.
+    pub fn count_transitions(&self) -> i64 {
+        if self.length < 2 {
+            return 0;
+        }
+        let a = self.slice(0, self.length - 1);
+        let b = self.slice(1, self.length);
+        a.__xor__(&b).unwrap().count()
+    }
+
+    /// Returns the number of agreements minus the number of disagreements between the bits and
+    /// the same bits shifted by lag, over the region where they overlap.
+    pub fn autocorrelation(&self, lag: i64) -> PyResult<i64> {
+        if lag < 0 || lag > self.length {
+            return Err(PyValueError::new_err("Lag must be between 0 and the length."));
+        }
+        let overlap = self.length - lag;
+        let a = self.slice(0, overlap);
+        let b = self.slice(lag, self.length);
+        let disagreements = a.__xor__(&b)?.count();
+        Ok(overlap - 2 * disagreements)
+    }
+
+    /// Returns the number of bits equal to value in the range [0, i).
+    pub fn rank(&self, i: i64, value: bool) -> PyResult<i64> {
+        if i < 0 || i > self.length {
+            return Err(PyIndexError::new_err("Out of range."));
+        }
+        let ones = self.slice(0, i).count();
+        Ok(if value { ones } else { i - ones })
+    }
+
+    /// Returns the index of the n-th (counting from zero) bit equal to value.
+    pub fn select(&self, n: i64, value: bool) -> PyResult<i64> {
+        if n >= 0 {
+            let mut remaining = n;
+            for i in 0..self.length {
+                if self.get_bit(i) == value {
+                    if remaining == 0 {
+                        return Ok(i);
+                    }
+                    remaining -= 1;
+                }
+            }
+        }
+        Err(PyValueError::new_err("Not enough bits with the given value."))
+    }
+
+    /// Returns a BitRust where each bit is the XOR of all the bits up to and including that position.
+    pub fn cumulative_xor(&self) -> Self {
+        let mut parity = false;
+        BitRust::from_bools((0..self.length).map(|i| {
+            parity ^= self.get_bit(i);
+            parity
+        }))
+    }
+
+    /// Returns a BitRust where each bit is the XOR of that bit and the previous one, with the first
+    /// bit unchanged. This is the inverse of cumulative_xor.
+    pub fn difference(&self) -> Self {
+        if self.length == 0 {
+            return self.clone();
+        }
+        let shifted = BitRust::join_internal(&[&BitRust::from_zeros(1), &self.slice(0, self.length - 1)]);
+        self.__xor__(&shifted).unwrap()
+    }
+
+    /// Returns the Shannon entropy per bit based on the proportion of set bits. This is 0.0 if all
+    /// bits are the same (or there are no bits) and 1.0 if there are equal numbers of zeros and ones.
+    pub fn shannon_entropy_bits(&self) -> f64 {
+        if self.length == 0 {
+            return 0.0;
+        }
+        let p = self.count() as f64 / self.length as f64;
+        if p == 0.0 || p == 1.0 {
+            return 0.0;
+        }
+        -p * p.log2() - (1.0 - p) * (1.0 - p).log2()
+    }
+
+    /// Splits into symbols of symbol_bits bits and returns the number of times each symbol value
+    /// occurs, indexed by the value.
+    pub fn symbol_histogram(&self, symbol_bits: i64) -> PyResult<Vec<i64>> {
+        if !(1..=16).contains(&symbol_bits) {
+            return Err(PyValueError::new_err("Symbol size must be between 1 and 16 bits."));
+        }
+        if self.length % symbol_bits != 0 {
+            return Err(PyValueError::new_err("Length is not a multiple of the symbol size."));
+        }
+        let mut counts: Vec<i64> = vec![0; 1 << symbol_bits];
+        for start in (0..self.length).step_by(symbol_bits as usize) {
+            counts[self.slice(start, start + symbol_bits).to_u64() as usize] += 1;
+        }
+        Ok(counts)
+    }
+
     /// Returns a new BitRust with all bits reversed.
     pub fn reverse(&self) -> Self {
-        let mut data: Vec<u8> = Vec::new();
-        for byte in self.data[self.start_byte()..self.end_byte()].iter().rev() {
-            data.push(byte.reverse_bits());
-        }
-        let final_bits = (self.offset + self.length) % 8;
-        let new_offset = if final_bits == 0 { 0 } else { 8 - final_bits };
+        let data: Vec<u8> = self.data[self.start_byte()..self.end_byte()].iter().rev()
+            .map(|byte| byte.reverse_bits())
+            .collect();
+        // The unused bits at the end of the final byte become the offset into the first byte.
+        let new_offset = (8 - (self.offset + self.length) % 8) % 8;
         BitRust {
             data: Arc::new(data),
             offset: new_offset,
             length: self.length,
+            cache: ReprCache::default(),
+        }
+    }
+
+    /// Shifts value in from the right, keeping the length fixed. Returns the new value and the
+    /// bits that were shifted out of the left.
+    pub fn shift_in_left(&self, value: &BitRust) -> (Self, Self) {
+        let joined = BitRust::join_internal(&[self, value]);
+        let shifted_out = joined.slice(0, value.length);
+        let new_value = joined.slice(value.length, joined.length);
+        (new_value, shifted_out)
+    }
+
+    /// Shifts left by one bit and inserts bit on the right, keeping the length fixed. Returns the new
+    /// value and the bit that was shifted out.
+    pub fn feed_bit(&self, bit: bool) -> (Self, bool) {
+        let bit = if bit { BitRust::from_ones(1) } else { BitRust::from_zeros(1) };
+        let (new_value, shifted_out) = self.shift_in_left(&bit);
+        (new_value, shifted_out.get_bit(0))
+    }
+
+    /// Returns the two's complement negation at the same length, wrapping on overflow. This is
+    /// the same as inverting all the bits and adding one.
+    pub fn neg(&self) -> Self {
+        // Bits up to and including the lowest set bit are unchanged, the rest are inverted.
+        match (0..self.length).rev().find(|&i| self.get_bit(i)) {
+            Some(lowest) => self.invert_range(0, lowest).unwrap(),
+            None => self.clone(),
+        }
+    }
+
+    pub fn __neg__(&self) -> Self {
+        self.neg()
+    }
+
+    /// Compares as unsigned integers, returning -1, 0 or 1. The lengths must match.
+    pub fn cmp_uint(&self, other: &BitRust) -> PyResult<i8> {
+        if self.length != other.length {
+            return Err(PyValueError::new_err("Lengths do not match."));
+        }
+        // Any padding bits are zero, so the bytes compare in the same order as the values.
+        Ok(self.to_bytes().cmp(&other.to_bytes()) as i8)
+    }
+
+    /// Treats both as unsigned integers and returns the absolute difference at the same length.
+    /// The lengths must match.
+    pub fn abs_diff(&self, other: &BitRust) -> PyResult<Self> {
+        if self.length != other.length {
+            return Err(PyValueError::new_err("Lengths do not match."));
+        }
+        // Any padding bits are zero, so comparing and subtracting the bytes works on the values.
+        let (a, b) = (self.to_bytes(), other.to_bytes());
+        let (larger, smaller) = if a >= b { (a, b) } else { (b, a) };
+        let mut diff = vec![0u8; larger.len()];
+        let mut borrow = false;
+        for i in (0..larger.len()).rev() {
+            let (d, b1) = larger[i].overflowing_sub(smaller[i]);
+            let (d, b2) = d.overflowing_sub(borrow as u8);
+            diff[i] = d;
+            borrow = b1 || b2;
+        }
+        Ok(BitRust::from_bytes(diff).slice(0, self.length))
+    }
+
+    /// Extends to new_length bits by adding copies of the most significant bit on the left.
+    pub fn sign_extend(&self, new_length: i64) -> PyResult<Self> {
+        let sign = self.length > 0 && self.get_bit(0);
+        self.extend_left(new_length, sign)
+    }
+
+    /// Extends to new_length bits by adding zero bits on the left.
+    pub fn zero_extend(&self, new_length: i64) -> PyResult<Self> {
+        self.extend_left(new_length, false)
+    }
+
+    /// Right-justifies in width bits by adding bits equal to value on the left.
+    pub fn rjust(&self, width: i64, value: bool) -> PyResult<Self> {
+        self.extend_left(width, value)
+    }
+
+    /// Left-justifies in width bits by adding bits equal to value on the right.
+    pub fn ljust(&self, width: i64, value: bool) -> PyResult<Self> {
+        if width < self.length {
+            return Err(PyValueError::new_err("New length is shorter than the current length."));
+        }
+        let extra = width - self.length;
+        let padding = if value { BitRust::from_ones(extra) } else { BitRust::from_zeros(extra) };
+        Ok(BitRust::join_internal(&[self, &padding]))
+    }
+
+    /// Pads with bits equal to value until the length is a multiple of multiple. The padding is
+    /// added on the left if left is true, otherwise on the right.
+    pub fn pad_to_multiple(&self, multiple: i64, left: bool, value: bool) -> PyResult<Self> {
+        if multiple <= 0 {
+            return Err(PyValueError::new_err("Multiple must be positive."));
+        }
+        let extra = (multiple - self.length % multiple) % multiple;
+        let padding = if value { BitRust::from_ones(extra) } else { BitRust::from_zeros(extra) };
+        if left {
+            Ok(BitRust::join_internal(&[&padding, self]))
+        } else {
+            Ok(BitRust::join_internal(&[self, &padding]))
+        }
+    }
+
+    /// Returns a slice with any leading bits equal to value removed.
+    pub fn lstrip(&self, value: bool) -> Self {
+        let mut start: i64 = 0;
+        while start < self.length && self.get_bit(start) == value {
+            start += 1;
+        }
+        self.slice(start, self.length)
+    }
+
+    /// Returns a slice with any trailing bits equal to value removed.
+    pub fn rstrip(&self, value: bool) -> Self {
+        let mut end: i64 = self.length;
+        while end > 0 && self.get_bit(end - 1) == value {
+            end -= 1;
+        }
+        self.slice(0, end)
+    }
+
+    /// Returns a slice with any leading and trailing bits equal to value removed.
+    pub fn strip(&self, value: bool) -> Self {
+        self.lstrip(value).rstrip(value)
+    }
+
+    /// Returns a new BitRust with the bits in each byte reversed, but with the byte order kept.
+    pub fn reverse_bits_in_bytes(&self) -> PyResult<Self> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        let data: Vec<u8> = self.to_bytes().iter().map(|b| b.reverse_bits()).collect();
+        Ok(BitRust::from_bytes(data))
+    }
+
+    /// Rotates whole bytes cyclically, to the left for positive n and to the right for negative n.
+    /// The length must be a whole number of bytes.
+    pub fn rotate_bytes(&self, n: i64) -> PyResult<Self> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        let mut data = self.to_bytes();
+        if !data.is_empty() {
+            let shift = n.rem_euclid(data.len() as i64) as usize;
+            data.rotate_left(shift);
+        }
+        Ok(BitRust::from_bytes(data))
+    }
+
+    /// Reverses the byte order within each word of word_bytes bytes, for example to convert between
+    /// big- and little-endian 32-bit words. The length must be a whole number of words.
+    pub fn swap_word_endianness(&self, word_bytes: usize) -> PyResult<Self> {
+        if word_bytes == 0 {
+            return Err(PyValueError::new_err("Word size must be positive."));
+        }
+        if self.length % (8 * word_bytes as i64) != 0 {
+            return Err(PyValueError::new_err("Not a whole number of words long."));
+        }
+        let mut data = self.to_bytes();
+        for word in data.chunks_mut(word_bytes) {
+            word.reverse();
+        }
+        Ok(BitRust::from_bytes(data))
+    }
+
+    /// Returns a copy with the bytes starting at byte_offset replaced by data. The length must be a
+    /// whole number of bytes and the replaced region must be within it.
+    pub fn overwrite_bytes(&self, byte_offset: usize, data: Vec<u8>) -> PyResult<Self> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        let mut bytes = self.to_bytes();
+        let end = byte_offset.checked_add(data.len())
+            .filter(|&e| e <= bytes.len())
+            .ok_or_else(|| PyIndexError::new_err("Data extends beyond the end."))?;
+        bytes[byte_offset..end].copy_from_slice(&data);
+        Ok(BitRust::from_bytes(bytes))
+    }
+
+    /// Reverses the order of the bits within each consecutive block of group bits, keeping the
+    /// order of the blocks. The length must be a multiple of group.
+    pub fn reverse_in_groups(&self, group: i64) -> PyResult<Self> {
+        if group <= 0 {
+            return Err(PyValueError::new_err("Group size must be positive."));
+        }
+        if self.length % group != 0 {
+            return Err(PyValueError::new_err("Length is not a multiple of the group size."));
         }
+        Ok(BitRust::from_bools((0..self.length).map(|i| {
+            let block_start = i / group * group;
+            self.get_bit(block_start + group - 1 - (i - block_start))
+        })))
     }
 
     /// Returns the bool value at a given bit index.
@@ -569,6 +2264,12 @@ impl BitRust {
         Ok(byte & (128 >> (p % 8)) != 0)
     }
     
+    /// Returns the bool value at a given bit index, or default if the index is out of range.
+    #[pyo3(signature = (bit_index, default=None))]
+    pub fn get(&self, bit_index: i64, default: Option<bool>) -> Option<bool> {
+        self.getindex(bit_index).ok().or(default)
+    }
+
     /// Returns the bit offset to the data in the Bits object.
     pub fn offset(&self) -> i64 {
         self.offset
@@ -586,25 +2287,80 @@ impl BitRust {
         &self.data
     }
 
+    /// Checks the internal invariants, raising an error if they don't hold. Bits outside of the
+    /// range given by the offset and length can have any value, so aren't checked.
+    pub fn validate(&self) -> PyResult<()> {
+        if self.offset < 0 {
+            return Err(PyValueError::new_err(format!("Negative offset {}.", self.offset)));
+        }
+        if self.length < 0 {
+            return Err(PyValueError::new_err(format!("Negative length {}.", self.length)));
+        }
+        if self.offset + self.length > self.data.len() as i64 * 8 {
+            return Err(PyValueError::new_err(format!("Offset {} plus length {} is more than the {} bits of data.",
+                                                     self.offset, self.length, self.data.len() * 8)));
+        }
+        Ok(())
+    }
+
+    /// Returns true if both objects use the same underlying buffer.
+    pub fn shares_storage_with(&self, other: &BitRust) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+
     /// Return a slice of the current BitRust. Uses a view on the current byte data.
-    #[pyo3(signature = (start_bit, end_bit=None))]
-    pub fn getslice(&self, start_bit: i64, end_bit: Option<i64>) -> PyResult<Self> {
-        let end_bit = end_bit.unwrap_or(self.length);
+    /// As with Python slicing, negative indices count from the end, out of range indices are
+    /// clamped and an empty BitRust is returned if the start isn't before the end.
+    /// A start_bit of None means the start, and an end_bit of None means the end.
+    #[pyo3(signature = (start_bit=None, end_bit=None))]
+    pub fn getslice(&self, start_bit: Option<i64>, end_bit: Option<i64>) -> PyResult<Self> {
+        let clamp = |i: i64| if i < 0 { (i + self.length).max(0) } else { i.min(self.length) };
+        let start_bit = clamp(start_bit.unwrap_or(0));
+        let end_bit = clamp(end_bit.unwrap_or(self.length));
         if start_bit >= end_bit {
             return Ok(BitRust::from_zeros(0)); // TODO: Use static instance for empty BitRust ?
         }
-        assert!(start_bit < end_bit);
-        if end_bit > self.length {
-            return Err(PyValueError::new_err("end bit goes past the end"));
-        }
         let new_length = end_bit - start_bit;
         Ok(BitRust {
             data: Arc::clone(&self.data),
             offset: start_bit + self.offset,
             length: new_length,
+            cache: ReprCache::default(),
+        })
+    }
+
+    /// Returns the bits in [start_bit, end_bit) in a newly allocated buffer with an offset of zero,
+    /// for when the result mustn't share storage with self.
+    pub fn slice_owned(&self, start_bit: i64, end_bit: i64) -> PyResult<Self> {
+        if start_bit < 0 || start_bit > end_bit || end_bit > self.length {
+            return Err(PyIndexError::new_err("Invalid range."));
+        }
+        Ok(self.slice(start_bit, end_bit).copy_with_new_offset(0))
+    }
+
+    /// Return a lightweight read-only view on a range of bits.
+    pub fn view(slf: &Bound<'_, Self>, start_bit: i64, end_bit: i64) -> PyResult<BitRustView> {
+        if start_bit < 0 || start_bit > end_bit || end_bit > slf.borrow().length {
+            return Err(PyValueError::new_err("Invalid range for view."));
+        }
+        Ok(BitRustView {
+            source: slf.clone().unbind(),
+            start: start_bit,
+            length: end_bit - start_bit,
         })
     }
 
+    /// Return a slice of whole bytes. The length must be a whole number of bytes.
+    pub fn getbyteslice(&self, start_byte: i64, end_byte: i64) -> PyResult<Self> {
+        if self.length % 8 != 0 {
+            return Err(PyValueError::new_err("Not a multiple of 8 bits long."));
+        }
+        if start_byte < 0 || start_byte > end_byte || end_byte > self.length / 8 {
+            return Err(PyIndexError::new_err("Byte range out of bounds."));
+        }
+        Ok(self.slice(start_byte * 8, end_byte * 8))
+    }
+
     // Return new BitRust with single bit flipped. If pos is None then flip all the bits.
     #[pyo3(signature = (pos=None))]
     pub fn invert(&self, pos: Option<i64>) -> Self {
@@ -626,9 +2382,30 @@ impl BitRust {
             data: Arc::new(data),
             offset: self.offset,
             length: self.length,
+            cache: ReprCache::default(),
         }
     }
 
+    /// Returns a copy with the bits in the range [start, end) flipped.
+    pub fn invert_range(&self, start: i64, end: i64) -> PyResult<Self> {
+        self.__xor__(&self.range_mask(start, end, true)?)
+    }
+
+    /// Returns a copy with the bits in the range [start, end) flipped. The same as invert_range.
+    pub fn toggle_range(&self, start: i64, end: i64) -> PyResult<Self> {
+        self.invert_range(start, end)
+    }
+
+    /// Returns a copy with the bits in the range [start, end) set to 1.
+    pub fn set_range(&self, start: i64, end: i64) -> PyResult<Self> {
+        self.__or__(&self.range_mask(start, end, true)?)
+    }
+
+    /// Returns a copy with the bits in the range [start, end) set to 0.
+    pub fn clear_range(&self, start: i64, end: i64) -> PyResult<Self> {
+        self.__and__(&self.range_mask(start, end, false)?)
+    }
+
     /// Returns true if all of the bits are set to 1.
     pub fn all_set(&self) -> bool {
         self.count() == self.length
@@ -672,6 +2449,7 @@ impl BitRust {
             data: Arc::new(data),
             offset: self.offset,
             length: self.length,
+            cache: ReprCache::default(),
         })
     }
 
@@ -681,19 +2459,30 @@ impl BitRust {
             data: Arc::new(self.active_data()),
             offset: self.offset % 8,
             length: self.length,
+            cache: ReprCache::default(),
         }
     }
 
     pub fn set_mutable_slice(&mut self, start: i64, end: i64, value: &BitRust) -> PyResult<()> {
-        let start_slice = self.getslice(0, Some(start))?;
-        let end_slice = self.getslice(end, Some(self.length))?;
-        let joined = BitRust::join_internal(&vec![&start_slice, value, &end_slice]);
+        let start_slice = self.getslice(Some(0), Some(start))?;
+        let end_slice = self.getslice(Some(end), Some(self.length))?;
+        let joined = BitRust::join_internal(&[&start_slice, value, &end_slice]);
         *self = joined;
         Ok(())
     }
 }
 
 
+/// Repeatable pseudo-random bytes for tests, from a simple linear congruential generator.
+#[cfg(test)]
+fn pseudo_random_bytes(seed: u32, n: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..n).map(|_| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        (state >> 16) as u8
+    }).collect()
+}
+
 // #[test]
 // fn new1() {
 //     let data: Vec<u8> = vec![10, 20, 30];
@@ -832,7 +2621,7 @@ fn get_index() {
 #[test]
 fn hex_edge_cases() {
     let b1 = BitRust::from_hex("0123456789abcdef").unwrap();
-    let b2 = b1.getslice(12, Some(b1.length())).unwrap();
+    let b2 = b1.getslice(Some(12), Some(b1.length())).unwrap();
     assert_eq!(b2.to_hex().unwrap(), "3456789abcdef");
     assert_eq!(b2.offset(), 12);
     assert_eq!(b2.length(), 52);
@@ -983,8 +2772,8 @@ fn test_set_mutable_slice() {
 #[test]
 fn test_getslice() {
     let a = BitRust::from_bin("00010001").unwrap();
-    assert_eq!(a.getslice(0, Some(4)).unwrap().to_bin(), "0001");
-    assert_eq!(a.getslice(4, Some(8)).unwrap().to_bin(), "0001");
+    assert_eq!(a.getslice(Some(0), Some(4)).unwrap().to_bin(), "0001");
+    assert_eq!(a.getslice(Some(4), Some(8)).unwrap().to_bin(), "0001");
 }
 
 #[test]
@@ -1004,4 +2793,1288 @@ fn test_set_index() {
     assert_eq!(b.to_bin(), "1000000001");
     let b = b.set_index(false, 0).unwrap();
     assert_eq!(b.to_bin(), "0000000001");
-}
\ No newline at end of file
+}
+#[test]
+fn test_sizeof() {
+    let a = BitRust::from_zeros(8000);
+    let b = a.getslice(Some(8), Some(16)).unwrap();
+    assert_eq!(a.__sizeof__(), b.__sizeof__());
+    assert!(a.__sizeof__() >= 1000);
+    let c = BitRust::from_zeros(16000);
+    assert!(c.__sizeof__() - a.__sizeof__() >= 1000);
+}
+
+#[test]
+fn test_shares_storage_with() {
+    let a = BitRust::from_hex("0123456789").unwrap();
+    let b = a.getslice(Some(8), Some(24)).unwrap();
+    assert!(a.shares_storage_with(&b));
+    let c = b.trim();
+    assert!(!c.shares_storage_with(&a));
+    assert_eq!(c, b);
+    assert!(!a.shares_storage_with(&BitRust::from_hex("0123456789").unwrap()));
+}
+
+#[test]
+fn test_bitstring_repr() {
+    let a = BitRust::from_hex("0a141e").unwrap();
+    assert_eq!(a.bitstring_repr(), "Bits('0x0a141e')");
+    let b = BitRust::from_bin("101").unwrap();
+    assert_eq!(b.bitstring_repr(), "Bits('0b101')");
+    let c = a.getslice(Some(4), Some(16)).unwrap();
+    assert_eq!(c.bitstring_repr(), "Bits('0xa14')");
+    assert_eq!(BitRust::from_zeros(0).bitstring_repr(), "Bits('0x')");
+}
+
+#[test]
+fn test_count_occurrences() {
+    let a = BitRust::from_bin("1111").unwrap();
+    let b = BitRust::from_bin("11").unwrap();
+    assert_eq!(a.count_occurrences(&b, false, true).unwrap(), 3);
+    assert_eq!(a.count_occurrences(&b, false, false).unwrap(), 2);
+    let c = BitRust::from_hex("ff0ff0ff").unwrap();
+    let d = BitRust::from_hex("ff").unwrap();
+    assert_eq!(c.count_occurrences(&d, false, false).unwrap(), 3);
+    assert_eq!(c.count_occurrences(&d, true, false).unwrap(), 2);
+    assert!(a.count_occurrences(&BitRust::from_zeros(0), false, true).is_err());
+}
+
+#[test]
+fn test_shift_in_left() {
+    let a = BitRust::from_bin("110011").unwrap();
+    let (b, out) = a.shift_in_left(&BitRust::from_bin("01").unwrap());
+    assert_eq!(b.to_bin(), "001101");
+    assert_eq!(out.to_bin(), "11");
+
+    let inputs = ["1010", "0011", "1111", "0000", "0110"];
+    let mut register = BitRust::from_zeros(8);
+    let mut history: Vec<BitRust> = vec![];
+    for s in inputs {
+        let (r, out) = register.shift_in_left(&BitRust::from_bin(s).unwrap());
+        register = r;
+        history.push(out);
+    }
+    assert_eq!(register.to_bin(), "00000110");
+    let shifted_out: Vec<String> = history.iter().map(|x| x.to_bin()).collect();
+    assert_eq!(shifted_out, vec!["0000", "0000", "1010", "0011", "1111"]);
+}
+
+#[test]
+fn test_from_bin_with_chars() {
+    let a = BitRust::from_bin_with_chars(".*.*", '.', '*').unwrap();
+    assert_eq!(a, BitRust::from_bin("0101").unwrap());
+    assert!(BitRust::from_bin_with_chars(".*0*", '.', '*').is_err());
+    assert!(BitRust::from_bin_with_chars("..", '.', '.').is_err());
+}
+
+#[test]
+fn test_diff() {
+    let a = BitRust::from_bin("0110100111").unwrap();
+    let b = BitRust::from_bin("0100101110").unwrap();
+    let expected: Vec<i64> = a.to_bin().chars().zip(b.to_bin().chars()).enumerate()
+        .filter(|(_, (x, y))| x != y)
+        .map(|(i, _)| i as i64)
+        .collect();
+    assert_eq!(a.diff(&b).unwrap(), expected);
+    assert_eq!(a.diff(&b).unwrap(), vec![2, 6, 9]);
+    assert!(a.diff(&a).unwrap().is_empty());
+    assert!(a.diff(&BitRust::from_zeros(3)).is_err());
+    assert!(a.diff(&BitRust::from_zeros(1)).is_err());
+}
+
+#[test]
+fn test_lstrip_rstrip() {
+    let a = BitRust::from_bin("0001100").unwrap();
+    assert_eq!(a.lstrip(false).to_bin(), "1100");
+    assert_eq!(a.rstrip(false).to_bin(), "00011");
+    assert_eq!(a.lstrip(true), a);
+    let b = BitRust::from_ones(12);
+    assert_eq!(b.lstrip(true).length(), 0);
+    assert_eq!(b.rstrip(true).length(), 0);
+    let c = BitRust::from_hex("00f00").unwrap().getslice(Some(4), Some(20)).unwrap();
+    assert_eq!(c.lstrip(false).to_bin(), "111100000000");
+}
+
+#[test]
+fn test_strip() {
+    let a = BitRust::from_bin("0011000").unwrap();
+    let b = a.strip(false);
+    assert_eq!(b.to_bin(), "11");
+    assert_eq!(b.length(), 2);
+    assert_eq!(a.strip(true), a);
+    assert_eq!(BitRust::from_zeros(9).strip(false).length(), 0);
+}
+
+#[test]
+fn test_contains() {
+    let needle = BitRust::from_hex("abcd").unwrap();
+    let end = BitRust::from_hex("12abcd").unwrap();
+    let big = BitRust::join_internal(&[&BitRust::from_zeros(4000), &end]);
+    assert!(big.__contains__(&needle));
+    assert_eq!(big.__contains__(&needle), big.find(&needle, 0, false).is_some());
+    assert_eq!(big.find(&needle, 0, true), Some(4008));
+    // Not byte aligned, so found by the bit-wise search.
+    let shifted = BitRust::join_internal(&[&BitRust::from_zeros(3), &needle]);
+    assert!(shifted.__contains__(&needle));
+    assert_eq!(shifted.find(&needle, 0, true), None);
+    assert!(!big.__contains__(&BitRust::from_hex("abce").unwrap()));
+}
+
+#[test]
+fn test_bytes_bitorder() {
+    let a = BitRust::from_hex("0180f3").unwrap();
+    let msb = a.to_bytes_bitorder(false).unwrap();
+    let lsb = a.to_bytes_bitorder(true).unwrap();
+    assert_eq!(msb, vec![0x01, 0x80, 0xf3]);
+    let reversed: Vec<u8> = msb.iter().map(|b| b.reverse_bits()).collect();
+    assert_eq!(lsb, reversed);
+    assert_eq!(BitRust::from_bytes_bitorder(lsb, true), a);
+    assert!(a.getslice(Some(0), Some(12)).unwrap().to_bytes_bitorder(true).is_err());
+}
+
+#[test]
+fn test_from_hex_lenient() {
+    let (a, consumed) = BitRust::from_hex_lenient("abXY");
+    assert_eq!(consumed, 2);
+    assert_eq!(a, BitRust::from_hex("ab").unwrap());
+    assert_eq!(a.length(), 8);
+    let (b, consumed) = BitRust::from_hex_lenient("123");
+    assert_eq!(consumed, 3);
+    assert_eq!(b.to_hex().unwrap(), "123");
+    let (c, consumed) = BitRust::from_hex_lenient("x12");
+    assert_eq!(consumed, 0);
+    assert_eq!(c.length(), 0);
+}
+
+#[test]
+fn test_iter() {
+    let a = BitRust::from_bin("0110100").unwrap().getslice(Some(1), None).unwrap();
+    let mut it = a.__iter__();
+    let mut first: Vec<bool> = vec![];
+    while let Some(b) = it.__next__() {
+        first.push(b);
+    }
+    assert_eq!(first, vec![true, true, false, true, false, false]);
+    let mut second: Vec<bool> = vec![];
+    let mut it = a.__iter__();
+    while let Some(b) = it.__next__() {
+        second.push(b);
+    }
+    assert_eq!(first, second);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn test_join_byte_aligned() {
+    let a = BitRust::from_hex("0102").unwrap();
+    let b = BitRust::from_hex("aabbccdd").unwrap().getslice(Some(8), Some(24)).unwrap();
+    let c = BitRust::from_zeros(0);
+    let before = JOIN_FAST_PATH_COUNT.load(Ordering::Relaxed);
+    let j = BitRust::join_internal(&[&a, &b, &c, &a]);
+    assert!(JOIN_FAST_PATH_COUNT.load(Ordering::Relaxed) > before);
+    assert_eq!(j.to_hex().unwrap(), "0102bbcc0102");
+    assert_eq!(j.offset(), 0);
+    let d = BitRust::from_bin("1").unwrap();
+    let j = BitRust::join_internal(&[&a, &d, &b]);
+    assert_eq!(j.to_bin(), format!("{}1{}", a.to_bin(), b.to_bin()));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn test_join_list_uses_fast_path() {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let a = Py::new(py, BitRust::from_hex("0102").unwrap()).unwrap();
+        let b = Py::new(py, BitRust::from_hex("aabbcc").unwrap().slice(8, 24)).unwrap();
+        for container in [PyList::new(py, [&a, &b]).unwrap().into_any(),
+                          PyTuple::new(py, [&a, &b]).unwrap().into_any()] {
+            let before = JOIN_FAST_PATH_COUNT.load(Ordering::Relaxed);
+            let j = BitRust::join(&container).unwrap();
+            assert!(JOIN_FAST_PATH_COUNT.load(Ordering::Relaxed) > before);
+            assert_eq!(j.to_hex().unwrap(), "0102bbcc");
+        }
+    });
+}
+
+#[test]
+fn test_to_bin_mapped() {
+    let a = BitRust::from_bin("1010").unwrap();
+    assert_eq!(a.to_bin_mapped('.', '*'), "*.*.");
+    let b = BitRust::from_bin_with_chars(&a.to_bin_mapped('.', '*'), '.', '*').unwrap();
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_builder() {
+    let mut builder = BitRustBuilder::new();
+    builder.feed_bytes(vec![1, 2]);
+    builder.feed_bytes(vec![]);
+    builder.feed_bytes(vec![3]);
+    builder.feed_bytes(vec![4, 5, 6]);
+    assert_eq!(builder.build(), BitRust::from_bytes(vec![1, 2, 3, 4, 5, 6]));
+}
+
+#[test]
+fn test_equals_padded() {
+    let a = BitRust::from_hex("abc").unwrap();
+    let padded = BitRust::from_bytes(a.to_bytes());
+    assert_eq!(padded.length(), 16);
+    assert!(a.equals_padded(&padded));
+    assert!(padded.equals_padded(&a));
+    assert!(!a.equals_padded(&BitRust::from_hex("abc1").unwrap()));
+    assert!(!a.equals_padded(&BitRust::from_hex("abd0").unwrap()));
+    assert!(a.equals_padded(&a));
+}
+
+#[test]
+fn test_inet_checksum() {
+    // IPv4 header with the checksum field zeroed.
+    let header = BitRust::from_hex("450000730000400040110000c0a80001c0a800c7").unwrap();
+    assert_eq!(header.inet_checksum().unwrap(), 0xb861);
+    // Including the checksum gives zero.
+    let header = BitRust::from_hex("45000073000040004011b861c0a80001c0a800c7").unwrap();
+    assert_eq!(header.inet_checksum().unwrap(), 0);
+    // Odd number of bytes is padded with zero.
+    let odd = BitRust::from_hex("0102ab").unwrap();
+    assert_eq!(odd.inet_checksum().unwrap(), BitRust::from_hex("0102ab00").unwrap().inet_checksum().unwrap());
+    assert!(BitRust::from_hex("123").unwrap().inet_checksum().is_err());
+}
+
+#[test]
+fn test_reverse_bits_in_bytes() {
+    let a = BitRust::from_hex("80").unwrap();
+    assert_eq!(a.reverse_bits_in_bytes().unwrap(), BitRust::from_hex("01").unwrap());
+    let b = BitRust::from_hex("f0801234").unwrap().getslice(Some(4), Some(28)).unwrap();
+    assert_eq!(b.reverse_bits_in_bytes().unwrap(), BitRust::from_hex("1080c4").unwrap());
+    assert!(BitRust::from_bin("101").unwrap().reverse_bits_in_bytes().is_err());
+}
+
+#[test]
+fn test_join_empty_elements() {
+    let empty = BitRust::from_bin("").unwrap();
+    let a = BitRust::from_bin("1010").unwrap();
+    let j = BitRust::join_internal(&[&empty, &a, &empty]);
+    assert_eq!(j.to_bin(), "1010");
+    assert_eq!(j.offset(), 0);
+    // An empty slice with a non-zero offset at the start.
+    let empty_slice = BitRust::from_hex("ff").unwrap().slice(5, 5);
+    let j = BitRust::join_internal(&[&empty_slice, &a, &empty_slice, &a]);
+    assert_eq!(j.to_bin(), "10101010");
+    assert_eq!(j.offset(), 0);
+    assert_eq!(BitRust::join_internal(&[&empty_slice, &empty]).length(), 0);
+}
+
+#[test]
+fn test_from_bin_partial_final_byte() {
+    let s = "1011010110101";
+    let b = BitRust::from_bin(s).unwrap();
+    assert_eq!(b.length(), 13);
+    assert_eq!(b.to_bin(), s);
+    assert_eq!(*b.data(), vec![0b10110101, 0b10101000]);
+}
+
+#[test]
+fn test_uint_ops() {
+    let a = BitRust::from_hex("a5").unwrap();
+    assert_eq!(a.and_uint(0x0f).unwrap().to_hex().unwrap(), "05");
+    assert_eq!(a.or_uint(0x0f).unwrap().to_hex().unwrap(), "af");
+    assert_eq!(a.xor_uint(0xff).unwrap().to_hex().unwrap(), "5a");
+    // Mask bits beyond the length are an error.
+    assert!(a.or_uint(0xf00).is_err());
+    assert!(a.and_uint(0x100).is_err());
+    assert!(BitRust::from_zeros(0).xor_uint(1).is_err());
+    assert_eq!(a.or_uint(0xff).unwrap().to_hex().unwrap(), "ff");
+    let b = BitRust::from_bin("10111").unwrap();
+    assert_eq!(b.and_uint(0b00110).unwrap().to_bin(), "00110");
+    assert!(BitRust::from_zeros(65).and_uint(1).is_err());
+    assert_eq!(BitRust::from_ones(64).xor_uint(u64::MAX).unwrap(), BitRust::from_zeros(64));
+}
+
+#[test]
+fn test_feed_bit() {
+    let a = BitRust::from_bin("1001101").unwrap();
+    let mut register = BitRust::from_zeros(7);
+    let mut ejected: Vec<bool> = vec![];
+    for i in 0..a.length() {
+        let (r, out) = register.feed_bit(a.getindex(i).unwrap());
+        register = r;
+        ejected.push(out);
+    }
+    assert_eq!(register, a);
+    assert!(ejected.iter().all(|&x| !x));
+    let (r, out) = register.feed_bit(false);
+    assert!(out);
+    assert_eq!(r.to_bin(), "0011010");
+}
+
+#[test]
+fn test_to_hex_offsets() {
+    let source = BitRust::from_hex("0123456789abcdef").unwrap();
+    for start in [0, 4, 8, 12, 16, 20] {
+        for length in [0, 4, 8, 12, 16, 20, 24] {
+            let b = source.getslice(Some(start), Some(start + length)).unwrap();
+            let expected = &"0123456789abcdef"[(start / 4) as usize..((start + length) / 4) as usize];
+            let h = b.to_hex().unwrap();
+            assert_eq!(h, expected, "start {} length {}", start, length);
+            assert_eq!(BitRust::from_hex(&h).unwrap(), b, "start {} length {}", start, length);
+        }
+    }
+}
+
+#[test]
+fn test_get() {
+    let b = BitRust::from_bin("1101").unwrap();
+    assert_eq!(b.get(0, None), Some(true));
+    assert_eq!(b.get(2, Some(true)), Some(false));
+    assert_eq!(b.get(-1, None), Some(true));
+    assert_eq!(b.get(-2, None), Some(false));
+    assert_eq!(b.get(4, None), None);
+    assert_eq!(b.get(4, Some(false)), Some(false));
+    assert_eq!(b.get(-5, Some(true)), Some(true));
+}
+
+#[test]
+fn test_sign_and_zero_extend() {
+    let a = BitRust::from_bin("1").unwrap();
+    assert_eq!(a.sign_extend(4).unwrap().to_bin(), "1111");
+    assert_eq!(a.zero_extend(4).unwrap().to_bin(), "0001");
+    let b = BitRust::from_bin("0110").unwrap();
+    assert_eq!(b.sign_extend(10).unwrap().to_bin(), "0000000110");
+    assert_eq!(b.sign_extend(4).unwrap(), b);
+    assert!(b.sign_extend(3).is_err());
+    assert!(b.zero_extend(2).is_err());
+}
+
+#[test]
+fn test_as_tuple() {
+    let a = BitRust::from_bin("1011001").unwrap();
+    let b = BitRust::from_bin("0001011001111").unwrap().getslice(Some(3), Some(10)).unwrap();
+    assert_eq!(a, b);
+    assert_ne!(a.offset(), b.offset());
+    assert_eq!(a.as_tuple(), b.as_tuple());
+    assert_eq!(a.as_tuple(), (vec![0b10110010], 7));
+    assert_ne!(a.as_tuple(), BitRust::from_bin("10110010").unwrap().as_tuple());
+}
+
+#[test]
+fn test_count_transitions() {
+    assert_eq!(BitRust::from_bin("0011").unwrap().count_transitions(), 1);
+    assert_eq!(BitRust::from_bin("0101").unwrap().count_transitions(), 3);
+    assert_eq!(BitRust::from_bin("1").unwrap().count_transitions(), 0);
+    assert_eq!(BitRust::from_zeros(0).count_transitions(), 0);
+    assert_eq!(BitRust::from_hex("00ff00").unwrap().count_transitions(), 2);
+}
+
+#[test]
+fn test_autocorrelation() {
+    // A maximal length sequence of period 15.
+    let m = BitRust::from_bin("100010011010111").unwrap();
+    assert_eq!(m.autocorrelation(0).unwrap(), 15);
+    let bin: Vec<char> = m.to_bin().chars().collect();
+    for lag in 0..=15 {
+        let expected: i64 = (0..15 - lag).map(|i| if bin[i] == bin[i + lag] { 1 } else { -1 }).sum();
+        assert_eq!(m.autocorrelation(lag as i64).unwrap(), expected);
+    }
+    let a = BitRust::from_bin("1110100").unwrap();
+    assert_eq!(a.autocorrelation(1).unwrap(), 0);
+    assert_eq!(a.autocorrelation(7).unwrap(), 0);
+    assert!(a.autocorrelation(8).is_err());
+    assert!(a.autocorrelation(-1).is_err());
+}
+
+#[test]
+fn test_pp() {
+    let a = BitRust::from_hex("0123456789abc").unwrap();
+    let s = a.pp(2).unwrap();
+    let lines: Vec<&str> = s.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "       0: 01 23  00000001 00100011");
+    assert_eq!(lines[1], "      16: 45 67  01000101 01100111");
+    assert_eq!(lines[2], "      32: 89 ab  10001001 10101011");
+    assert_eq!(lines[3], "      48: c      1100");
+    assert_eq!(BitRust::from_zeros(0).pp(4).unwrap(), "");
+    assert!(a.pp(0).is_err());
+    assert_eq!(a.pp(4096).unwrap().lines().count(), 1);
+    assert!(a.pp(4097).is_err());
+    assert!(a.pp(1 << 63).is_err());
+}
+
+#[test]
+fn test_getslice_with_step() {
+    let s = "1100101110";
+    let a = BitRust::from_bin(s).unwrap();
+    let every_other: String = s.chars().step_by(2).collect();
+    assert_eq!(a.getslice_with_step(0, 2, 5).to_bin(), every_other);
+    let odd: String = s.chars().skip(1).step_by(2).collect();
+    assert_eq!(a.getslice_with_step(1, 2, 5).to_bin(), odd);
+    let reversed: String = s.chars().rev().collect();
+    assert_eq!(a.getslice_with_step(9, -1, 10).to_bin(), reversed);
+    assert_eq!(a.getslice_with_step(2, 1, 4).to_bin(), "0010");
+    assert_eq!(a.getslice_with_step(0, 3, 0).length(), 0);
+}
+
+#[test]
+fn test_rank() {
+    let a = BitRust::from_bin("1011001110").unwrap();
+    assert_eq!(a.rank(5, true).unwrap(), 3);
+    assert_eq!(a.rank(5, false).unwrap(), 2);
+    assert_eq!(a.rank(0, true).unwrap(), 0);
+    assert_eq!(a.rank(10, true).unwrap(), a.count());
+    assert!(a.rank(11, true).is_err());
+    let b = BitRust::from_hex("ff00ff").unwrap().getslice(Some(4), None).unwrap();
+    assert_eq!(b.rank(12, true).unwrap(), 4);
+}
+
+#[test]
+fn test_select() {
+    let a = BitRust::from_hex("0001000080000100").unwrap();
+    assert_eq!(a.select(0, true).unwrap(), 15);
+    assert_eq!(a.select(1, true).unwrap(), 32);
+    assert_eq!(a.select(2, true).unwrap(), 55);
+    assert!(a.select(3, true).is_err());
+    assert_eq!(a.select(15, false).unwrap(), 16);
+    for n in 0..3 {
+        let i = a.select(n, true).unwrap();
+        assert_eq!(a.rank(i, true).unwrap(), n);
+    }
+}
+
+#[test]
+fn test_bitwise_broadcast() {
+    let a = BitRust::from_hex("a5c").unwrap();
+    let one = BitRust::from_bin("1").unwrap();
+    let zero = BitRust::from_bin("0").unwrap();
+    assert_eq!(a.__and__(&one).unwrap(), a);
+    assert_eq!(a.__and__(&zero).unwrap(), BitRust::from_zeros(12));
+    assert_eq!(one.__and__(&a).unwrap(), a);
+    assert_eq!(a.__or__(&one).unwrap(), BitRust::from_ones(12));
+    assert_eq!(a.__xor__(&one).unwrap(), a.invert(None));
+    assert_eq!(one.__xor__(&zero).unwrap(), one);
+    assert!(a.__and__(&BitRust::from_bin("11").unwrap()).is_err());
+}
+
+#[test]
+fn test_byte_chunks() {
+    let a = BitRust::from_hex("00112233445566778899").unwrap();
+    for bits in [a.clone(), a.getslice(Some(8), None).unwrap(), BitRust::join_internal(&[&BitRust::from_bin("1").unwrap(), &a]).slice(1, 81)] {
+        let mut chunks = bits.byte_chunks(3).unwrap();
+        let mut joined: Vec<u8> = vec![];
+        while let Some(chunk) = chunks.__next__() {
+            assert!(chunk.len() <= 3);
+            joined.extend(chunk);
+        }
+        assert_eq!(joined, bits.to_bytes());
+    }
+    assert!(a.getslice(Some(0), Some(4)).unwrap().byte_chunks(1).is_err());
+    assert!(a.byte_chunks(0).is_err());
+    let mut it = a.byte_chunks(usize::MAX).unwrap();
+    assert_eq!(it.__next__().unwrap(), a.to_bytes());
+    assert!(it.__next__().is_none());
+    assert!(BitRust::from_zeros(0).byte_chunks(2).unwrap().__next__().is_none());
+}
+
+#[test]
+fn test_match_mask() {
+    let a = BitRust::from_bin("1100101").unwrap();
+    let b = BitRust::from_bin("1010110").unwrap();
+    let m = a.match_mask(&b).unwrap();
+    assert_eq!(m.to_bin(), "1001100");
+    assert_eq!(m, a.__xor__(&b).unwrap().invert(None));
+    assert_eq!(a.match_mask(&a).unwrap(), BitRust::from_ones(7));
+    assert!(a.match_mask(&BitRust::from_bin("1").unwrap()).is_err());
+}
+
+#[test]
+fn test_from_bin_multi_byte_partial() {
+    let b = BitRust::from_bin("101").unwrap();
+    assert_eq!(*b.data(), vec![0b10100000]);
+    let b = BitRust::from_bin("110000001").unwrap();
+    assert_eq!(b.length(), 9);
+    assert_eq!(*b.data(), vec![0b11000000, 0b10000000]);
+    assert_eq!(b.to_bin(), "110000001");
+    let s = "10000000010000000011";
+    let b = BitRust::from_bin(s).unwrap();
+    assert_eq!(*b.data(), vec![0b10000000, 0b01000000, 0b00110000]);
+    assert_eq!(b.to_bin(), s);
+}
+
+#[test]
+fn test_join_sliced_first_element() {
+    let source = BitRust::from_hex("0123456789abcdef").unwrap();
+    let others = [BitRust::from_bin("101").unwrap(), BitRust::from_hex("f0f").unwrap(), source.slice(3, 17)];
+    for start in 0..40 {
+        let first = source.slice(start, start + 12);
+        for other in &others {
+            let j = BitRust::join_internal(&[&first, other]);
+            assert_eq!(j.to_bin(), format!("{}{}", first.to_bin(), other.to_bin()), "start {}", start);
+            let j = BitRust::join_internal(&[&first, other, &first]);
+            assert_eq!(j.to_bin(), format!("{}{}{}", first.to_bin(), other.to_bin(), first.to_bin()), "start {}", start);
+        }
+    }
+}
+
+#[test]
+fn test_cumulative_xor() {
+    let a = BitRust::from_bin("1101001").unwrap();
+    let c = a.cumulative_xor();
+    assert_eq!(c.to_bin(), "1001110");
+    // Differencing the running parity gets back the original.
+    let shifted = BitRust::join_internal(&[&BitRust::from_zeros(1), &c.slice(0, c.length() - 1)]);
+    assert_eq!(c.__xor__(&shifted).unwrap(), a);
+    assert_eq!(c.difference(), a);
+    assert_eq!(BitRust::from_zeros(0).cumulative_xor().length(), 0);
+}
+
+#[test]
+fn test_difference() {
+    assert_eq!(BitRust::from_bin("1001110").unwrap().difference().to_bin(), "1101001");
+    assert_eq!(BitRust::from_zeros(0).difference().length(), 0);
+    let x = BitRust::from_bytes(pseudo_random_bytes(12345, 50));
+    for (start, end) in [(0, 400), (3, 397), (5, 6), (17, 250)] {
+        let y = x.slice(start, end);
+        assert_eq!(y.difference().cumulative_xor(), y);
+        assert_eq!(y.cumulative_xor().difference(), y);
+    }
+}
+
+#[test]
+fn test_validate() {
+    let a = BitRust::from_hex("0123456789").unwrap();
+    for b in [a.clone(), a.getslice(Some(3), Some(30)).unwrap(), a.reverse(), a.trim(), BitRust::from_zeros(0),
+              BitRust::from_bin("101").unwrap(), BitRust::join_internal(&[&a, &BitRust::from_bin("1").unwrap()])] {
+        assert!(b.validate().is_ok());
+    }
+    let too_long = BitRust { data: Arc::new(vec![0, 0]), offset: 4, length: 13, cache: ReprCache::default() };
+    assert!(too_long.validate().is_err());
+    let negative = BitRust { data: Arc::new(vec![0]), offset: -1, length: 1, cache: ReprCache::default() };
+    assert!(negative.validate().is_err());
+}
+
+#[test]
+fn test_gather() {
+    let a = BitRust::from_bin("10110").unwrap();
+    assert_eq!(a.gather(&[0, 2, 4]).unwrap().to_bin(), "110");
+    assert_eq!(a.gather(&[-1, 1, 1]).unwrap().to_bin(), "000");
+    assert_eq!(a.gather(&[]).unwrap().length(), 0);
+    assert!(a.gather(&[5]).is_err());
+}
+
+#[test]
+fn test_bitstring_state() {
+    let a = BitRust::from_hex("0123456789").unwrap().getslice(Some(11), Some(29)).unwrap();
+    let (data, offset, length) = a.to_bitstring_state();
+    assert_eq!(data.len(), 5);
+    let b = BitRust::from_state(data, offset, length).unwrap();
+    assert_eq!(b, a);
+    assert_eq!(b.offset(), 11);
+    assert_eq!(b.length(), 18);
+    assert!(BitRust::from_state(vec![0], 4, 5).is_err());
+}
+
+#[test]
+fn test_getbyteslice() {
+    let a = BitRust::from_hex("0011223344").unwrap();
+    assert_eq!(a.getbyteslice(1, 3).unwrap().to_hex().unwrap(), "1122");
+    assert_eq!(a.getbyteslice(0, 5).unwrap(), a);
+    assert_eq!(a.getbyteslice(2, 2).unwrap().length(), 0);
+    assert!(a.getbyteslice(3, 6).is_err());
+    assert!(a.getbyteslice(3, 2).is_err());
+    assert!(a.getbyteslice(0, 1 << 62).is_err());
+    assert!(a.getbyteslice(0, i64::MAX).is_err());
+    let b = a.getslice(Some(4), Some(36)).unwrap();
+    assert_eq!(b.getbyteslice(1, 2).unwrap().to_hex().unwrap(), "12");
+    assert!(a.getslice(Some(0), Some(12)).unwrap().getbyteslice(0, 1).is_err());
+}
+
+#[test]
+fn test_xor_repeating() {
+    let a = BitRust::from_hex("123456").unwrap();
+    let key = BitRust::from_hex("ff").unwrap();
+    let tiled = BitRust::join_internal(&[&key, &key, &key]);
+    assert_eq!(a.xor_repeating(&key).unwrap(), a.__xor__(&tiled).unwrap());
+    let b = BitRust::from_bin("0000000").unwrap();
+    assert_eq!(b.xor_repeating(&BitRust::from_bin("10").unwrap()).unwrap().to_bin(), "1010101");
+    assert_eq!(BitRust::from_zeros(0).xor_repeating(&key).unwrap().length(), 0);
+    assert!(a.xor_repeating(&BitRust::from_zeros(0)).is_err());
+}
+
+#[test]
+fn test_reverse_large() {
+    let big = BitRust::from_bytes(pseudo_random_bytes(987, 4000));
+    for (start, end) in [(0, 32000), (3, 31999), (13, 29011), (8, 16), (5, 6), (7, 7)] {
+        let b = big.slice(start, end);
+        let naive: String = b.to_bin().chars().rev().collect();
+        let r = b.reverse();
+        assert_eq!(r.to_bin(), naive, "{} {}", start, end);
+        assert!(r.offset() < 8);
+    }
+}
+
+#[test]
+fn test_bin_prefix() {
+    let a = BitRust::from_hex("f0a5c3").unwrap().getslice(Some(3), None).unwrap();
+    let full = a.to_bin();
+    for n in 0..=a.length() {
+        assert_eq!(a.bin_prefix(n).unwrap(), full[..n as usize]);
+    }
+    assert!(a.bin_prefix(a.length() + 1).is_err());
+    assert!(a.bin_prefix(-1).is_err());
+}
+
+#[test]
+fn test_combine() {
+    let a = BitRust::from_bin("0011").unwrap();
+    let b = BitRust::from_bin("0101").unwrap();
+    assert_eq!(a.combine(&b, 0b1000).unwrap(), a.__and__(&b).unwrap());
+    assert_eq!(a.combine(&b, 0b0110).unwrap(), a.__xor__(&b).unwrap());
+    assert_eq!(a.combine(&b, 0b1110).unwrap(), a.__or__(&b).unwrap());
+    // Each truth table applied to all four input combinations gives back the table.
+    for t in 0..16 {
+        let out = a.combine(&b, t).unwrap();
+        let table: u8 = (0..4).map(|i| (out.getindex(i).unwrap() as u8) << i).sum();
+        assert_eq!(table, t);
+    }
+    let c = BitRust::from_hex("a5f0c").unwrap().getslice(Some(3), None).unwrap();
+    let d = BitRust::from_hex("3c").unwrap().getslice(Some(0), Some(7)).unwrap();
+    let c = c.getslice(Some(0), Some(7)).unwrap();
+    assert_eq!(c.combine(&d, 0b0111).unwrap(), c.__and__(&d).unwrap().invert(None));
+    assert!(a.combine(&b, 16).is_err());
+    assert!(a.combine(&BitRust::from_bin("1").unwrap(), 8).is_err());
+}
+
+#[test]
+fn test_majority() {
+    let a = BitRust::from_bin("110").unwrap();
+    let b = BitRust::from_bin("101").unwrap();
+    let c = BitRust::from_bin("011").unwrap();
+    assert_eq!(BitRust::majority_internal(&[&a, &b, &c]).unwrap().to_bin(), "111");
+    // Ties are resolved to zero.
+    assert_eq!(BitRust::majority_internal(&[&a, &b]).unwrap().to_bin(), "100");
+    assert_eq!(BitRust::majority_internal(&[&a]).unwrap(), a);
+    assert!(BitRust::majority_internal(&[]).is_err());
+    assert!(BitRust::majority_internal(&[&a, &BitRust::from_bin("1").unwrap()]).is_err());
+}
+
+#[test]
+fn test_getslice_python_semantics() {
+    let s = "0001101011";
+    let a = BitRust::from_bin(s).unwrap();
+    assert_eq!(a.getslice(Some(-4), Some(-1)).unwrap().to_bin(), &s[6..9]);
+    assert_eq!(a.getslice(Some(5), Some(2)).unwrap().length(), 0);
+    assert_eq!(a.getslice(Some(-100), Some(3)).unwrap().to_bin(), &s[..3]);
+    assert_eq!(a.getslice(Some(7), Some(100)).unwrap().to_bin(), &s[7..]);
+    assert_eq!(a.getslice(Some(-3), None).unwrap().to_bin(), &s[7..]);
+    assert_eq!(a.getslice(Some(20), None).unwrap().length(), 0);
+    assert_eq!(a.getslice(None, Some(8)).unwrap().to_bin(), &s[..8]);
+    assert_eq!(a.getslice(Some(8), None).unwrap().to_bin(), &s[8..]);
+    assert_eq!(a.getslice(None, Some(-2)).unwrap().to_bin(), &s[..8]);
+    assert_eq!(a.getslice(None, None).unwrap(), a);
+}
+
+#[test]
+fn test_shannon_entropy_bits() {
+    assert_eq!(BitRust::from_zeros(100).shannon_entropy_bits(), 0.0);
+    assert_eq!(BitRust::from_ones(100).shannon_entropy_bits(), 0.0);
+    assert_eq!(BitRust::from_zeros(0).shannon_entropy_bits(), 0.0);
+    assert!((BitRust::from_hex("a5a5").unwrap().shannon_entropy_bits() - 1.0).abs() < 1e-12);
+    let quarter = BitRust::from_hex("8888").unwrap().shannon_entropy_bits();
+    assert!((quarter - 0.8112781244591328).abs() < 1e-12);
+}
+
+#[test]
+fn test_symbol_histogram() {
+    let a = BitRust::from_hex("1212f1").unwrap();
+    let h = a.symbol_histogram(4).unwrap();
+    assert_eq!(h.len(), 16);
+    assert_eq!(h[1], 3);
+    assert_eq!(h[2], 2);
+    assert_eq!(h[15], 1);
+    assert_eq!(h.iter().sum::<i64>(), 6);
+    let h = BitRust::from_bin("0110").unwrap().symbol_histogram(1).unwrap();
+    assert_eq!(h, vec![2, 2]);
+    assert!(a.symbol_histogram(5).is_err());
+    assert!(a.symbol_histogram(0).is_err());
+    assert!(BitRust::from_zeros(34).symbol_histogram(17).is_err());
+}
+
+#[test]
+fn test_popcount_per_byte() {
+    let a = BitRust::from_hex("0fff").unwrap();
+    assert_eq!(a.popcount_per_byte().unwrap(), vec![4, 8]);
+    let b = BitRust::from_hex("f0ff80").unwrap().getslice(Some(4), Some(20)).unwrap();
+    assert_eq!(b.popcount_per_byte().unwrap(), vec![4, 5]);
+    assert!(a.getslice(Some(0), Some(12)).unwrap().popcount_per_byte().is_err());
+}
+
+#[test]
+fn test_extended() {
+    let a = BitRust::from_bin("101").unwrap();
+    let b = BitRust::from_hex("ff").unwrap();
+    let (c, length) = a.extended(&b);
+    assert_eq!(length, c.length());
+    assert_eq!(length, 11);
+    assert_eq!(c.to_bin(), "10111111111");
+}
+
+#[test]
+fn test_matches_at() {
+    let a = BitRust::from_hex("0ff00f").unwrap();
+    let p = BitRust::from_hex("ff").unwrap();
+    assert!(a.matches_at(4, &p).unwrap());
+    assert!(!a.matches_at(3, &p).unwrap());
+    assert!(a.matches_at(6, &BitRust::from_bin("111111").unwrap()).unwrap());
+    assert!(!a.matches_at(6, &BitRust::from_bin("110000").unwrap()).unwrap());
+    assert!(!a.matches_at(16, &p).unwrap());
+    assert!(a.matches_at(17, &p).is_err());
+    assert!(a.matches_at(-1, &p).is_err());
+    assert!(a.matches_at(i64::MAX, &p).is_err());
+}
+
+#[test]
+fn test_reverse_in_groups() {
+    let a = BitRust::from_bin("00010010").unwrap();
+    assert_eq!(a.reverse_in_groups(4).unwrap().to_bin(), "10000100");
+    assert_eq!(a.reverse_in_groups(8).unwrap(), a.reverse());
+    assert_eq!(a.reverse_in_groups(1).unwrap(), a);
+    assert!(a.reverse_in_groups(3).is_err());
+    assert!(a.reverse_in_groups(0).is_err());
+}
+
+#[test]
+fn test_read_exp_golomb() {
+    // Codewords for 0 to 4 are 1, 010, 011, 00100, 00101.
+    let bits = BitRust::from_bin("101001100100001010001000").unwrap();
+    let mut reader = BitRustReader::new(&bits);
+    let values: Vec<u64> = (0..5).map(|_| reader.read_ue().unwrap()).collect();
+    assert_eq!(values, vec![0, 1, 2, 3, 4]);
+    assert_eq!(reader.pos(), 17);
+    // 0001000 is code number 7, which is signed value 4.
+    assert_eq!(reader.read_se().unwrap(), 4);
+    assert_eq!(reader.pos(), 24);
+    assert!(reader.read_ue().is_err());
+
+    let bits = BitRust::from_bin("1010011001000010100").unwrap();
+    let mut reader = BitRustReader::new(&bits);
+    let values: Vec<i64> = (0..5).map(|_| reader.read_se().unwrap()).collect();
+    assert_eq!(values, vec![0, 1, -1, 2, -2]);
+
+    let mut reader = BitRustReader::new(&BitRust::from_bin("0001").unwrap());
+    assert!(reader.read_ue().is_err());
+    assert_eq!(reader.pos(), 0);
+}
+
+#[test]
+fn test_reader_byte_align() {
+    let bits = BitRust::from_hex("abcd1").unwrap();
+    let mut reader = BitRustReader::new(&bits);
+    assert_eq!(reader.byte_align(), 0);
+    assert_eq!(reader.read(5).unwrap().to_bin(), "10101");
+    assert_eq!(reader.byte_align(), 3);
+    assert_eq!(reader.pos(), 8);
+    assert_eq!(reader.byte_align(), 0);
+    reader.read(9).unwrap();
+    assert_eq!(reader.byte_align(), 3);
+    assert_eq!(reader.pos(), 20);
+}
+
+#[test]
+fn test_matmul_hamming_7_4() {
+    let rows: Vec<BitRust> = ["1000", "0100", "0010", "0001", "1101", "1011", "0111"]
+        .iter().map(|r| BitRust::from_bin(r).unwrap()).collect();
+    let rows: Vec<&BitRust> = rows.iter().collect();
+    let message = BitRust::from_bin("1011").unwrap();
+    assert_eq!(message.parity_products(&rows).unwrap().to_bin(), "1011010");
+    let message = BitRust::from_bin("0000").unwrap();
+    assert_eq!(message.parity_products(&rows).unwrap().to_bin(), "0000000");
+    let bad_row = BitRust::from_bin("1").unwrap();
+    assert!(message.parity_products(&[&bad_row]).is_err());
+}
+
+#[test]
+fn test_windowed_popcount() {
+    let a = BitRust::from_hex("ff0f01e").unwrap();
+    assert_eq!(a.windowed_popcount(8).unwrap(), vec![8, 4, 1, 3]);
+    assert_eq!(a.windowed_popcount(28).unwrap(), vec![16]);
+    assert_eq!(a.windowed_popcount(100).unwrap(), vec![16]);
+    assert!(BitRust::from_zeros(0).windowed_popcount(8).unwrap().is_empty());
+    assert!(a.windowed_popcount(0).is_err());
+}
+
+#[test]
+fn test_findall_max_matches() {
+    let a = BitRust::from_bin("1010101010").unwrap();
+    let b = BitRust::from_bin("10").unwrap();
+    assert_eq!(a.findall_list(&b, false, None, None, 1 << 20).unwrap(), vec![0, 2, 4, 6, 8]);
+    assert_eq!(a.findall_list(&b, false, Some(2), None, 1 << 20).unwrap(), vec![0, 2]);
+    assert_eq!(a.findall_list(&b, false, Some(10), None, 1 << 20).unwrap().len(), 5);
+    assert!(a.findall_list(&b, false, Some(0), None, 1 << 20).unwrap().is_empty());
+}
+
+#[test]
+fn test_to_bytes_exact() {
+    let a = BitRust::from_hex("abcd").unwrap();
+    assert_eq!(a.to_bytes_exact().unwrap(), vec![0xab, 0xcd]);
+    assert!(a.slice(0, 12).to_bytes_exact().is_err());
+    assert_eq!(BitRust::from_hex("abcdef").unwrap().slice(4, 20).to_bytes_exact().unwrap(), vec![0xbc, 0xde]);
+}
+
+#[test]
+fn test_join_many() {
+    let source = BitRust::from_hex("a5c3f0961e").unwrap();
+    let parts: Vec<BitRust> = (0..10000)
+        .map(|i: i64| {
+            let start = i % 13;
+            source.slice(start, start + (i * 7) % 23)
+        })
+        .collect();
+    let refs: Vec<&BitRust> = parts.iter().collect();
+    let joined = BitRust::join_internal(&refs);
+    let expected: String = parts.iter().map(|p| p.to_bin()).collect();
+    assert_eq!(joined.length(), expected.len() as i64);
+    assert_eq!(joined.to_bin(), expected);
+    assert!(joined.data.capacity() * 8 < joined.length() as usize + 16);
+
+    let parts: Vec<BitRust> = (0..10000).map(|i: i64| source.slice(8 * (i % 4), 8 * (i % 4) + 8)).collect();
+    let refs: Vec<&BitRust> = parts.iter().collect();
+    let expected: String = parts.iter().map(|p| p.to_bin()).collect();
+    assert_eq!(BitRust::join_internal(&refs).to_bin(), expected);
+}
+
+#[test]
+fn test_to_oct_offset_slices() {
+    // 0o1234567012 is 001 010 011 100 101 110 111 000 001 010
+    let a = BitRust::from_oct("1234567012").unwrap();
+    for start in 0..10 {
+        for end in (start..=30).filter(|end| (end - start) % 3 == 0) {
+            let b = a.getslice(Some(start), Some(end)).unwrap();
+            let oct = b.to_oct().unwrap();
+            assert_eq!(oct.len() as i64 * 3, b.length());
+            assert_eq!(BitRust::from_oct(&oct).unwrap(), b);
+        }
+    }
+    assert_eq!(a.getslice(Some(3), Some(12)).unwrap().to_oct().unwrap(), "234");
+    assert_eq!(a.getslice(Some(1), Some(10)).unwrap().to_oct().unwrap(), "247");
+    assert_eq!(a.getslice(Some(5), Some(29)).unwrap().to_oct().unwrap(), "16273405");
+    assert!(a.getslice(Some(1), Some(9)).unwrap().to_oct().is_err());
+}
+
+#[test]
+fn test_pad_to_multiple() {
+    let a = BitRust::from_hex("abcde").unwrap();
+    let b = a.pad_to_multiple(16, false, false).unwrap();
+    assert_eq!(b.length(), 32);
+    assert_eq!(b.to_hex().unwrap(), "abcde000");
+    assert_eq!(a.pad_to_multiple(16, true, true).unwrap().to_hex().unwrap(), "fffabcde");
+    assert_eq!(a.pad_to_multiple(4, false, true).unwrap(), a);
+    assert_eq!(BitRust::from_zeros(0).pad_to_multiple(8, false, true).unwrap().length(), 0);
+    assert!(a.pad_to_multiple(0, false, false).is_err());
+}
+
+#[test]
+fn test_parse_token() {
+    assert_eq!(BitRust::parse_token("uint:12").unwrap(), ("uint", 12));
+    assert_eq!(BitRust::parse_token("hex: 8").unwrap(), ("hex", 8));
+    assert_eq!(BitRust::parse_token("bool").unwrap(), ("bool", 1));
+    assert_eq!(BitRust::parse_token("bool:1").unwrap(), ("bool", 1));
+    assert!(BitRust::parse_token("bool:2").is_err());
+    assert!(BitRust::parse_token("uint").is_err());
+    assert!(BitRust::parse_token("uint:0").is_err());
+    assert!(BitRust::parse_token("float:32").is_err());
+}
+
+#[test]
+fn test_find_empty_needle() {
+    let a = BitRust::from_hex("f0f").unwrap();
+    let empty = BitRust::from_zeros(0);
+    assert_eq!(a.find(&empty, 0, false), Some(0));
+    assert_eq!(a.find(&empty, 0, true), Some(0));
+    assert_eq!(a.find(&empty, 3, true), Some(0));
+    assert_eq!(a.find(&empty, 12, false), Some(0));
+    assert_eq!(a.find(&empty, 13, false), None);
+    assert_eq!(empty.find(&empty, 0, false), Some(0));
+}
+
+#[test]
+fn test_rotate_bytes() {
+    let a = BitRust::from_hex("0102030405").unwrap();
+    assert_eq!(a.rotate_bytes(2).unwrap(), BitRust::from_hex("0304050102").unwrap());
+    assert_eq!(a.rotate_bytes(-1).unwrap(), BitRust::from_hex("0501020304").unwrap());
+    assert_eq!(a.rotate_bytes(7).unwrap(), a.rotate_bytes(2).unwrap());
+    assert_eq!(a.rotate_bytes(0).unwrap(), a);
+    assert_eq!(BitRust::from_zeros(0).rotate_bytes(3).unwrap().length(), 0);
+    assert!(a.slice(0, 12).rotate_bytes(1).is_err());
+}
+
+#[test]
+fn test_overwrite_bytes() {
+    let a = BitRust::from_hex("0011223344").unwrap();
+    assert_eq!(a.overwrite_bytes(1, vec![0xaa, 0xbb]).unwrap().to_hex().unwrap(), "00aabb3344");
+    assert_eq!(a.overwrite_bytes(3, vec![0xff, 0xee]).unwrap().to_hex().unwrap(), "001122ffee");
+    assert_eq!(a.overwrite_bytes(5, vec![]).unwrap(), a);
+    assert_eq!(a.to_hex().unwrap(), "0011223344");
+    assert!(a.overwrite_bytes(4, vec![1, 2]).is_err());
+    assert!(a.overwrite_bytes(usize::MAX, vec![1]).is_err());
+    assert_eq!(a.slice(4, 36).overwrite_bytes(0, vec![1]).unwrap().to_hex().unwrap(), "01122334");
+    assert!(a.slice(0, 12).overwrite_bytes(0, vec![1]).is_err());
+}
+
+#[test]
+fn test_fold_to_length() {
+    let ones = BitRust::from_ones(12);
+    assert_eq!(ones.fold_to_length(12).unwrap(), ones);
+    // The second half is padded with zeros, so only the overlapping bits cancel.
+    assert_eq!(ones.fold_to_length(8).unwrap().to_bin(), "00001111");
+    assert_eq!(BitRust::from_ones(7).fold_to_length(4).unwrap().to_bin(), "0001");
+    // a5c3 -> a5 ^ c3 = 66 -> 6 ^ 6 = 0 -> 00
+    let a = BitRust::from_hex("a5c3").unwrap();
+    assert_eq!(a.fold_to_length(8).unwrap().to_hex().unwrap(), "66");
+    assert_eq!(a.fold_to_length(2).unwrap().to_bin(), "00");
+    // 01100110 -> 01100 ^ 11000
+    assert_eq!(a.fold_to_length(5).unwrap().to_bin(), "10100");
+    // An even fold of all ones cancels completely.
+    assert_eq!(BitRust::from_ones(16).fold_to_length(8).unwrap(), BitRust::from_zeros(8));
+    // 1011011 -> 1011 ^ 0110 (011 padded with a zero)
+    let b = BitRust::from_bin("1011011").unwrap();
+    assert_eq!(b.fold_to_length(4).unwrap().to_bin(), "1101");
+    // 110100111 -> 11010 ^ 01110 = 10100 -> 101 ^ 000 = 101 -> 10 ^ 10 = 00
+    let c = BitRust::from_bin("110100111").unwrap();
+    assert_eq!(c.fold_to_length(5).unwrap().to_bin(), "10100");
+    assert_eq!(c.fold_to_length(3).unwrap().to_bin(), "101");
+    assert_eq!(c.fold_to_length(2).unwrap().to_bin(), "00");
+    assert!(a.fold_to_length(0).is_err());
+    assert!(a.fold_to_length(17).is_err());
+}
+
+#[test]
+fn test_count_ones_masked() {
+    let a = BitRust::from_hex("f0f3c5a9e").unwrap();
+    let mask = BitRust::from_hex("3cc30ff01").unwrap();
+    for (start, end) in [(0, 36), (3, 29), (5, 6), (9, 36), (0, 0)] {
+        let x = a.slice(start, end);
+        let m = mask.slice(36 - (end - start), 36);
+        assert_eq!(x.count_ones_masked(&m).unwrap(), x.__and__(&m).unwrap().count());
+    }
+    // Every combination of offsets, including ones that differ within a byte.
+    for x_start in 0..12 {
+        for m_start in 0..12 {
+            for len in [0, 1, 7, 8, 9, 17, 24] {
+                let x = a.slice(x_start, x_start + len);
+                let m = mask.slice(m_start, m_start + len);
+                assert_eq!(x.count_ones_masked(&m).unwrap(), x.__and__(&m).unwrap().count());
+            }
+        }
+    }
+    assert_eq!(a.count_ones_masked(&BitRust::from_ones(36)).unwrap(), a.count());
+    assert!(a.count_ones_masked(&BitRust::from_ones(1)).is_err());
+}
+
+#[test]
+fn test_invert_range() {
+    let a = BitRust::from_hex("f00f").unwrap();
+    assert_eq!(a.invert_range(4, 12).unwrap().to_hex().unwrap(), "ffff");
+    assert_eq!(a.invert_range(6, 6).unwrap(), a);
+    assert_eq!(a.invert_range(0, 16).unwrap().to_hex().unwrap(), "0ff0");
+    let b = BitRust::from_hex("00f00f").unwrap().slice(8, 24);
+    assert_eq!(b.invert_range(1, 3).unwrap().to_bin(), "1001000000001111");
+    assert!(a.invert_range(3, 17).is_err());
+    assert!(a.invert_range(5, 4).is_err());
+    assert!(a.invert_range(-1, 4).is_err());
+}
+
+#[test]
+fn test_fletcher16() {
+    assert_eq!(BitRust::from_bytes(b"abcde".to_vec()).fletcher16().unwrap(), 0xc8f0);
+    assert_eq!(BitRust::from_bytes(b"abcdef".to_vec()).fletcher16().unwrap(), 0x2057);
+    assert_eq!(BitRust::from_bytes(b"abcdefgh".to_vec()).fletcher16().unwrap(), 0x0627);
+    assert_eq!(BitRust::from_zeros(0).fletcher16().unwrap(), 0);
+    assert!(BitRust::from_zeros(12).fletcher16().is_err());
+}
+
+#[test]
+fn test_neg() {
+    let one = BitRust::from_u64(1, 8);
+    assert_eq!(one.neg(), BitRust::from_hex("ff").unwrap());
+    assert_eq!(one.neg().neg(), one);
+    assert_eq!(BitRust::from_hex("80").unwrap().neg().to_hex().unwrap(), "80");
+    assert_eq!(BitRust::from_zeros(12).neg(), BitRust::from_zeros(12));
+    assert_eq!(BitRust::from_hex("0c8").unwrap().neg().to_hex().unwrap(), "f38");
+    let a = BitRust::from_hex("5a3c7").unwrap().slice(3, 17);
+    assert_eq!(a.neg().neg(), a);
+    assert_eq!((a.neg().to_u64() + a.to_u64()) % (1 << 14), 0);
+    assert_eq!(BitRust::from_zeros(0).neg().length(), 0);
+}
+
+#[test]
+fn test_abs_diff() {
+    let a = BitRust::from_u64(1000, 12);
+    let b = BitRust::from_u64(3001, 12);
+    assert_eq!(a.abs_diff(&b).unwrap().to_u64(), 2001);
+    assert_eq!(b.abs_diff(&a).unwrap(), a.abs_diff(&b).unwrap());
+    assert_eq!(a.abs_diff(&a).unwrap(), BitRust::from_zeros(12));
+    let c = BitRust::from_hex("00ff00").unwrap().slice(3, 22);
+    let d = BitRust::from_hex("ff0001").unwrap().slice(3, 22);
+    assert_eq!(c.abs_diff(&d).unwrap().to_u64(), d.to_u64() - c.to_u64());
+    assert_eq!(d.abs_diff(&c).unwrap().length(), 19);
+    assert!(a.abs_diff(&BitRust::from_zeros(8)).is_err());
+}
+
+#[test]
+fn test_cmp_uint() {
+    let a = BitRust::from_u64(1000, 12);
+    let b = BitRust::from_u64(3001, 12);
+    assert_eq!(a.cmp_uint(&b).unwrap(), -1);
+    assert_eq!(b.cmp_uint(&a).unwrap(), 1);
+    assert_eq!(a.cmp_uint(&a.slice(0, 12)).unwrap(), 0);
+    let c = BitRust::from_hex("00ff00ff").unwrap().slice(5, 30);
+    let d = BitRust::from_hex("00ff0100").unwrap().slice(5, 30);
+    assert_eq!(c.cmp_uint(&d).unwrap(), -1);
+    assert_eq!(d.cmp_uint(&c).unwrap(), 1);
+    assert_eq!(BitRust::from_zeros(0).cmp_uint(&BitRust::from_zeros(0)).unwrap(), 0);
+    assert!(a.cmp_uint(&BitRust::from_zeros(8)).is_err());
+}
+
+#[test]
+fn test_hex_chunks() {
+    let a = BitRust::from_hex("0123456789abcdef0").unwrap().slice(4, 68);
+    for size in [1, 3, 5, 16, 100] {
+        let mut it = a.hex_chunks(size).unwrap();
+        let chunks: Vec<String> = std::iter::from_fn(|| it.__next__()).collect();
+        assert_eq!(chunks.concat(), a.to_hex().unwrap());
+        assert!(chunks.iter().all(|c| c.len() <= size));
+        assert_eq!(chunks.len(), 16_usize.div_ceil(size));
+    }
+    assert!(BitRust::from_zeros(0).hex_chunks(4).unwrap().__next__().is_none());
+    assert!(a.slice(0, 6).hex_chunks(4).is_err());
+    assert!(a.hex_chunks(0).is_err());
+    let mut it = a.hex_chunks(1 << 61).unwrap();
+    assert_eq!(it.__next__().unwrap(), a.to_hex().unwrap());
+    assert!(it.__next__().is_none());
+    assert_eq!(a.hex_chunks(usize::MAX).unwrap().__next__().unwrap(), a.to_hex().unwrap());
+}
+
+#[test]
+fn test_subrange_equals() {
+    let a = BitRust::from_hex("0ff00f").unwrap();
+    let p = BitRust::from_hex("ff").unwrap();
+    assert!(a.subrange_equals(4, &p));
+    assert!(!a.subrange_equals(5, &p));
+    assert!(a.subrange_equals(20, &BitRust::from_hex("f").unwrap()));
+    assert!(!a.subrange_equals(17, &p));
+    assert!(!a.subrange_equals(100, &p));
+    assert!(!a.subrange_equals(-1, &p));
+    assert!(!a.subrange_equals(i64::MAX, &p));
+}
+
+#[test]
+fn test_bcd() {
+    assert_eq!(BitRust::from_hex("1234").unwrap().to_bcd().unwrap(), 1234);
+    assert_eq!(BitRust::from_hex("0099").unwrap().to_bcd().unwrap(), 99);
+    assert!(BitRust::from_hex("1a").unwrap().to_bcd().is_err());
+    assert!(BitRust::from_bin("100").unwrap().to_bcd().is_err());
+    assert!(BitRust::from_hex("99999999999999999999").unwrap().to_bcd().is_err());
+    assert_eq!(BitRust::from_zeros(0).to_bcd().unwrap(), 0);
+    assert_eq!(BitRust::from_bcd(1234, 16).unwrap(), BitRust::from_hex("1234").unwrap());
+    assert_eq!(BitRust::from_bcd(7, 12).unwrap().to_hex().unwrap(), "007");
+    assert_eq!(BitRust::from_bcd(u64::MAX, 80).unwrap().to_bcd().unwrap(), u64::MAX);
+    assert!(BitRust::from_bcd(12345, 16).is_err());
+    assert!(BitRust::from_bcd(1, 6).is_err());
+}
+
+#[test]
+fn test_bit_count() {
+    let a = BitRust::from_hex("f0f3c5a9e").unwrap();
+    assert_eq!(a.bit_count(), a.count());
+    assert_eq!(a.slice(3, 29).bit_count(), a.slice(3, 29).count());
+    assert_eq!(BitRust::from_zeros(0).bit_count(), 0);
+}
+
+#[test]
+fn test_rjust_ljust() {
+    let a = BitRust::from_bin("11").unwrap();
+    assert_eq!(a.rjust(5, false).unwrap().to_bin(), "00011");
+    assert_eq!(a.rjust(4, true).unwrap().to_bin(), "1111");
+    assert_eq!(a.ljust(5, false).unwrap().to_bin(), "11000");
+    assert_eq!(BitRust::from_bin("0").unwrap().ljust(3, true).unwrap().to_bin(), "011");
+    assert_eq!(a.rjust(2, false).unwrap(), a);
+    assert_eq!(a.ljust(2, false).unwrap(), a);
+    assert!(a.rjust(1, false).is_err());
+    assert!(a.ljust(1, false).is_err());
+}
+
+#[test]
+fn test_swap_word_endianness() {
+    let a = BitRust::from_hex("0102030405060708").unwrap();
+    assert_eq!(a.swap_word_endianness(4).unwrap().to_hex().unwrap(), "0403020108070605");
+    assert_eq!(a.swap_word_endianness(2).unwrap().to_hex().unwrap(), "0201040306050807");
+    assert_eq!(a.swap_word_endianness(1).unwrap(), a);
+    assert_eq!(a.swap_word_endianness(4).unwrap().swap_word_endianness(4).unwrap(), a);
+    assert!(a.swap_word_endianness(3).is_err());
+    assert!(a.swap_word_endianness(0).is_err());
+    assert!(a.slice(0, 12).swap_word_endianness(1).is_err());
+}
+
+#[test]
+fn test_from_hex_large() {
+    let digits = "0123456789abcdefABCDEF";
+    let hex: String = (0..100_001).map(|i| digits.as_bytes()[(i * 7) % digits.len()] as char).collect();
+    let a = BitRust::from_hex(&hex).unwrap();
+    assert_eq!(a.length(), 400_004);
+    assert_eq!(a.data.len(), 50_001);
+    assert_eq!(a.to_hex().unwrap(), hex.to_lowercase());
+    let even = BitRust::from_hex(&hex[..100_000]).unwrap();
+    assert_eq!(even.to_bytes(), hex::decode(&hex[..100_000]).unwrap());
+    assert_eq!(BitRust::from_hex("f").unwrap().to_bin(), "1111");
+    assert_eq!(BitRust::from_hex("").unwrap().length(), 0);
+    assert!(BitRust::from_hex("abg").is_err());
+    assert!(BitRust::from_hex("ag1").is_err());
+    assert!(BitRust::from_hex("é").is_err());
+}
+
+#[test]
+fn test_tally() {
+    let a = BitRust::from_hex("f0f3c5a9e").unwrap().slice(3, 29);
+    let (zeros, ones) = a.tally();
+    assert_eq!(zeros + ones, a.length());
+    assert_eq!(ones, a.count());
+    assert_eq!(BitRust::from_zeros(9).tally(), (9, 0));
+    assert_eq!(BitRust::from_ones(9).tally(), (0, 9));
+}
+
+#[test]
+fn test_append_to() {
+    let source = BitRust::from_hex("a5c3f0961e").unwrap();
+    let parts: Vec<BitRust> = (0..500).map(|i: i64| source.slice(i % 13, i % 13 + (i * 5) % 19)).collect();
+    let mut data: Vec<u8> = Vec::new();
+    let mut length: i64 = 0;
+    for bits in &parts {
+        BitRust::append_to(&mut data, length, bits);
+        length += bits.length();
+    }
+    let joined = BitRust { data: Arc::new(data), offset: 0, length, cache: ReprCache::default() };
+    let refs: Vec<&BitRust> = parts.iter().collect();
+    assert_eq!(joined, BitRust::join_internal(&refs));
+}
+
+#[test]
+fn test_repr_cache() {
+    let a = BitRust::from_hex("0f3a5").unwrap();
+    assert!(a.cache.bin.get().is_none());
+    assert_eq!(a.to_bin(), a.compute_bin());
+    assert_eq!(a.cache.bin.get().unwrap(), "00001111001110100101");
+    assert_eq!(a.to_hex().unwrap(), "0f3a5");
+    assert_eq!(a.to_hex().unwrap(), a.compute_hex());
+    // Derived values have their own caches.
+    let b = a.slice(4, 16);
+    assert!(b.cache.hex.get().is_none());
+    assert_eq!(b.to_hex().unwrap(), "f3a");
+    assert_eq!(b.to_bin(), "111100111010");
+    assert_eq!(a.invert(None).to_hex().unwrap(), "f0c5a");
+    assert_eq!(a.clone().cache.bin.get(), None);
+    assert_eq!(a.to_hex().unwrap(), "0f3a5");
+    // A failed conversion isn't cached.
+    let c = a.slice(0, 6);
+    assert!(c.to_hex().is_err());
+    assert!(c.cache.hex.get().is_none());
+    // Internal uses don't fill the cache.
+    let d = BitRust::from_hex("a5c3f0").unwrap();
+    assert!(d == d.clone());
+    d.__and__(&d.clone()).unwrap();
+    d.to_oct().unwrap();
+    d.to_bin_mapped('.', '#');
+    assert!(d.cache.bin.get().is_none());
+    assert!(d.cache.hex.get().is_none());
+    // repr uses the cache when the whole value is shown.
+    d.__repr__();
+    assert_eq!(d.cache.hex.get().unwrap(), "a5c3f0");
+    // Replacing a slice replaces the cache too.
+    let mut e = BitRust::from_hex("ff").unwrap();
+    assert_eq!(e.to_bin(), "11111111");
+    e.set_mutable_slice(0, 4, &BitRust::from_zeros(4)).unwrap();
+    assert!(e.cache.bin.get().is_none());
+    assert_eq!(e.to_bin(), "00001111");
+}
+
+#[test]
+fn test_ifind_all() {
+    let a = BitRust::from_hex("0f0f0f").unwrap().slice(2, 24);
+    let b = BitRust::from_bin("0011").unwrap();
+    let mut it = a.ifind_all(&b, false);
+    let found: Vec<i64> = std::iter::from_fn(|| it.__next__()).collect();
+    assert_eq!(found, a.find_all_rust(&b, false).collect::<Vec<i64>>());
+    assert_eq!(found, vec![0, 8, 16]);
+    assert!(it.__next__().is_none());
+}
+
+#[test]
+fn test_popcount_table() {
+    for (i, &count) in POPCOUNT_TABLE.iter().enumerate() {
+        assert_eq!(count as u32, (i as u8).count_ones());
+        assert_eq!(byte_count_ones(i as u8), count as u32);
+    }
+    let bytes = pseudo_random_bytes(12345, 4096);
+    let table_count: u32 = bytes.iter().map(|&b| POPCOUNT_TABLE[b as usize] as u32).sum();
+    let intrinsic_count: u32 = bytes.iter().map(|b| b.count_ones()).sum();
+    assert_eq!(table_count, intrinsic_count);
+    let a = BitRust::from_bytes(bytes);
+    assert_eq!(a.count(), intrinsic_count as i64);
+    assert_eq!(a.slice(3, 32765).count(), a.slice(3, 32765).to_bin().matches('1').count() as i64);
+}
+
+#[test]
+fn test_slice_owned() {
+    let a = BitRust::from_hex("0123456789").unwrap();
+    for (start, end) in [(0, 40), (8, 24), (3, 29), (12, 12)] {
+        let b = a.slice_owned(start, end).unwrap();
+        assert_eq!(b.offset(), 0);
+        assert!(!b.shares_storage_with(&a));
+        assert_eq!(b, a.slice(start, end));
+        assert_eq!(b.data.len(), ((end - start + 7) / 8) as usize);
+    }
+    assert!(a.slice_owned(5, 41).is_err());
+    assert!(a.slice_owned(6, 5).is_err());
+    assert!(a.slice_owned(-1, 5).is_err());
+}
+
+#[test]
+fn test_range_operations() {
+    let a = BitRust::from_hex("a53c96").unwrap();
+    assert_eq!(a.set_range(4, 20).unwrap().to_hex().unwrap(), "affff6");
+    assert_eq!(a.clear_range(4, 20).unwrap().to_hex().unwrap(), "a00006");
+    assert_eq!(a.toggle_range(4, 20).unwrap().to_hex().unwrap(), "aac366");
+    assert_eq!(a.toggle_range(4, 20).unwrap(), a.invert_range(4, 20).unwrap());
+    // A range that isn't nibble aligned, on a slice with an offset.
+    let b = a.slice(3, 21);
+    let set = b.set_range(5, 11).unwrap();
+    assert_eq!(set.slice(0, 5), b.slice(0, 5));
+    assert!(set.slice(5, 11).all_set());
+    assert_eq!(set.slice(11, 18), b.slice(11, 18));
+    let cleared = b.clear_range(5, 11).unwrap();
+    assert_eq!(cleared.slice(0, 5), b.slice(0, 5));
+    assert!(!cleared.slice(5, 11).any_set());
+    assert_eq!(cleared.slice(11, 18), b.slice(11, 18));
+    assert_eq!(b.set_range(7, 7).unwrap(), b);
+    assert_eq!(b.clear_range(0, 18).unwrap(), BitRust::from_zeros(18));
+    assert!(a.set_range(0, 25).is_err());
+    assert!(a.clear_range(5, 4).is_err());
+    assert!(a.toggle_range(-1, 4).is_err());
+    let one = BitRust::from_bin("1").unwrap();
+    assert_eq!(one.clear_range(0, 1).unwrap().to_bin(), "0");
+}
+
+#[test]
+fn test_detect_and_parse() {
+    // Unprefixed strings of 0 and 1 default to binary.
+    assert_eq!(BitRust::detect_and_parse("10").unwrap().to_bin(), "10");
+    assert_eq!(BitRust::detect_and_parse("0x10").unwrap().to_bin(), "00010000");
+    assert_eq!(BitRust::detect_and_parse("0B101").unwrap().to_bin(), "101");
+    assert_eq!(BitRust::detect_and_parse("0o17").unwrap().to_bin(), "001111");
+    assert_eq!(BitRust::detect_and_parse("12").unwrap().to_hex().unwrap(), "12");
+    assert_eq!(BitRust::detect_and_parse("beef").unwrap().to_hex().unwrap(), "beef");
+    assert_eq!(BitRust::detect_and_parse("0x").unwrap().length(), 0);
+    // A prefix decides the format, so invalid digits for it are an error.
+    assert!(BitRust::detect_and_parse("0b12").is_err());
+    assert!(BitRust::detect_and_parse("0o8").is_err());
+    assert!(BitRust::detect_and_parse("xyz").is_err());
+    assert!(BitRust::detect_and_parse("").is_err());
+    assert!(BitRust::detect_and_parse("é1").is_err());
+}
@@ -1,8 +1,21 @@
+// Lints raised by the existing code, which is left as it is.
+#![allow(clippy::needless_range_loop, clippy::bool_comparison)]
+// The existing tests compare bools with assert_eq!.
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
 pub mod bits;
+#[cfg(feature = "simd")]
+pub mod simd;
 use pyo3::prelude::*;
 
 #[pymodule]
 fn bit_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<bits::BitRust>()?;
+    m.add_class::<bits::BitRustIterator>()?;
+    m.add_class::<bits::BitRustBuilder>()?;
+    m.add_class::<bits::BitRustView>()?;
+    m.add_class::<bits::BitRustReader>()?;
+    m.add_class::<bits::BytesChunkIterator>()?;
+    m.add_class::<bits::HexChunkIterator>()?;
+    m.add_class::<bits::FindAllIterator>()?;
     Ok(())
 }
\ No newline at end of file
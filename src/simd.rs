@@ -0,0 +1,48 @@
+//! Optional SIMD implementations, enabled with the `simd` feature.
+
+/// Returns the number of set bits in a slice of bytes, using AVX2 if it's available.
+pub fn count_ones(bytes: &[u8]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        return unsafe { count_ones_avx2(bytes) };
+    }
+    hamming::weight(bytes)
+}
+
+/// Popcount of 32 bytes at a time, by looking up the count for each nibble with a shuffle
+/// and then summing the byte counts.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn count_ones_avx2(bytes: &[u8]) -> u64 {
+    use std::arch::x86_64::*;
+    let lookup = _mm256_setr_epi8(0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+                                  0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4);
+    let low_mask = _mm256_set1_epi8(0x0f);
+    let mut total = _mm256_setzero_si256();
+    let chunks = bytes.chunks_exact(32);
+    let remainder = chunks.remainder();
+    for chunk in chunks {
+        let v = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        let low = _mm256_and_si256(v, low_mask);
+        let high = _mm256_and_si256(_mm256_srli_epi16(v, 4), low_mask);
+        let counts = _mm256_add_epi8(_mm256_shuffle_epi8(lookup, low), _mm256_shuffle_epi8(lookup, high));
+        total = _mm256_add_epi64(total, _mm256_sad_epu8(counts, _mm256_setzero_si256()));
+    }
+    let mut lanes = [0u64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, total);
+    lanes.iter().sum::<u64>() + hamming::weight(remainder)
+}
+
+#[test]
+fn test_count_ones_matches_default() {
+    let mut state: u32 = 2024;
+    let mut data: Vec<u8> = vec![];
+    for _ in 0..100_003 {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        data.push((state >> 16) as u8);
+    }
+    for (start, end) in [(0, 100_003), (1, 100_000), (7, 39), (0, 31), (5, 5)] {
+        assert_eq!(count_ones(&data[start..end]), hamming::weight(&data[start..end]));
+    }
+    assert_eq!(count_ones(&[0xff; 1000]), 8000);
+}
@@ -29,6 +29,19 @@ def test_join():
     e = c & d
     assert e.to_bin() == '00000000'
 
+def test_join_iterable():
+    source = BitRust.from_hex('a5c3f0961e')
+    parts = [source.getslice(i % 13, i % 13 + (i * 7) % 23) for i in range(1000)]
+    expected = BitRust.join(parts)
+    assert expected.to_bin() == ''.join(p.to_bin() for p in parts)
+    assert BitRust.join(p for p in parts) == expected
+    assert BitRust.join(tuple(parts)) == expected
+    assert BitRust.join(iter([])).length() == 0
+    with pytest.raises(TypeError):
+        BitRust.join([source, '0b1'])
+    with pytest.raises(TypeError):
+        BitRust.join(5)
+
 def test_find():
     a = BitRust.from_bin('00000110001110')
     b = BitRust.from_bin('11')
@@ -47,3 +60,343 @@ def test_to_bytes():
     assert a.to_bytes() == b"\xff\xff"
     b = a.getslice(7, None);
     assert b.to_bytes() == b"\xff\x80"
+
+def test_from_numpy():
+    np = pytest.importorskip("numpy")
+    arr = np.array([1, 2, 3, 255], dtype=np.uint8)
+    a = BitRust.from_numpy(arr)
+    assert a == BitRust.from_bytes(b'\x01\x02\x03\xff')
+    with pytest.raises(ValueError):
+        BitRust.from_numpy(np.array([1, 2, 3], dtype=np.int32))
+    with pytest.raises(ValueError):
+        BitRust.from_numpy(np.arange(10, dtype=np.uint8)[::2])
+
+def test_from_numpy_copies():
+    # Any buffer of unsigned bytes is accepted, so this doesn't need numpy.
+    buf = bytearray(b'\x01\x02')
+    a = BitRust.from_numpy(buf)
+    buf[0] = 0xff
+    assert a.to_bytes() == b'\x01\x02'
+
+def test_to_numpy():
+    np = pytest.importorskip("numpy")
+    a = BitRust.from_hex('0102ff')
+    arr = a.to_numpy()
+    assert arr.dtype == np.uint8
+    assert list(arr) == [1, 2, 255]
+    assert list(a.getslice(4, 20).to_numpy()) == [0x10, 0x2f]
+    # The array is writable and doesn't share memory with the BitRust.
+    assert arr.flags.writeable
+    arr[0] = 7
+    assert a.to_bytes() == b'\x01\x02\xff'
+    with pytest.raises(ValueError):
+        a.getslice(0, 12).to_numpy()
+
+def test_to_bool_array():
+    np = pytest.importorskip("numpy")
+    a = BitRust.from_hex('a5f').getslice(3, 11)
+    arr = a.to_bool_array()
+    assert arr.dtype == np.bool_
+    assert len(arr) == a.length()
+    assert list(arr) == [c == '1' for c in a.to_bin()]
+    assert arr.flags.writeable
+    arr[0] = not arr[0]
+    assert len(BitRust.from_zeros(0).to_bool_array()) == 0
+
+def test_from_bool_array():
+    np = pytest.importorskip("numpy")
+    a = BitRust.from_hex('a5f').getslice(3, 11)
+    assert BitRust.from_bool_array(a.to_bool_array()) == a
+    assert BitRust.from_bool_array(np.array([True, False, True])).to_bin() == '101'
+    assert BitRust.from_bool_array(np.array([True, False, True, True])[::2]).to_bin() == '11'
+    with pytest.raises(ValueError):
+        BitRust.from_bool_array(np.array([1, 0, 1], dtype=np.uint8))
+    with pytest.raises(ValueError):
+        BitRust.from_bool_array(np.zeros((2, 2), dtype=bool))
+
+def test_from_bin_with_chars():
+    a = BitRust.from_bin('.*.*', zero='.', one='*')
+    assert a.to_bin() == '0101'
+    with pytest.raises(ValueError):
+        BitRust.from_bin('01', zero='.', one='*')
+
+def test_from_bin_with_length():
+    assert BitRust.from_bin('101', length=3).to_bin() == '101'
+    with pytest.raises(ValueError):
+        BitRust.from_bin('101', length=4)
+    with pytest.raises(ValueError):
+        BitRust.from_bin('101', length=2)
+
+def test_iter():
+    a = BitRust.from_bin('1011')
+    assert list(a) == [True, False, True, True]
+    assert list(a) == [True, False, True, True]
+    it = iter(a)
+    assert next(it) is True
+    assert list(iter(a)) == [True, False, True, True]
+
+def test_builder():
+    from bit_rust import BitRustBuilder
+    builder = BitRustBuilder()
+    for chunk in [b'ab', b'c', b'de']:
+        builder.feed_bytes(chunk)
+    assert builder.build() == BitRust.from_bytes(b'abcde')
+
+def test_view():
+    a = BitRust.from_hex('0123456789')
+    v = a.view(6, 30)
+    s = a.getslice(6, 30)
+    assert v.length() == s.length()
+    assert v.to_bin() == s.to_bin()
+    assert [v.getindex(i) for i in range(v.length())] == [s.getindex(i) for i in range(s.length())]
+    assert v.getindex(-1) == s.getindex(-1)
+    with pytest.raises(IndexError):
+        v.getindex(24)
+    with pytest.raises(ValueError):
+        a.view(10, 50)
+
+def test_view_of_shortened_source():
+    a = BitRust.from_hex('0123456789')
+    v = a.view(6, 30)
+    a.set_mutable_slice(8, 40, BitRust.from_zeros(0))
+    with pytest.raises(IndexError):
+        v.getindex(20)
+    with pytest.raises(IndexError):
+        v.getindex(0)
+    with pytest.raises(IndexError):
+        v.to_bin()
+    w = a.view(0, 8)
+    assert w.to_bin() == '00000001'
+
+def test_getitem():
+    s = '1100101110'
+    a = BitRust.from_bin(s)
+    assert a[0] is True
+    assert a[-1] is False
+    assert a[2:6].to_bin() == s[2:6]
+    assert a[::2].to_bin() == s[::2]
+    assert a[1::2].to_bin() == s[1::2]
+    assert a[::-1].to_bin() == s[::-1]
+    assert a[-3:1:-2].to_bin() == s[-3:1:-2]
+    assert a[5:2].to_bin() == ''
+
+def test_getitem_exceptions():
+    a = BitRust.from_bin('1100101110')
+    for i in [10, -11, 1000, 2 ** 70, -2 ** 70]:
+        with pytest.raises(IndexError):
+            a[i]
+    with pytest.raises(IndexError):
+        a.getindex(10)
+    with pytest.raises(IndexError):
+        a[[0, 10]]
+    with pytest.raises(TypeError):
+        a['1']
+    with pytest.raises(TypeError):
+        a[1.0]
+    assert a[True] is True
+    assert a[5:100].to_bin() == '01110'
+    assert a[-100:2].to_bin() == '11'
+    assert a[100:200].to_bin() == ''
+    assert a[100:-100:-3].to_bin() == '0101'
+
+def test_getitem_shares_storage():
+    a = BitRust.from_hex('0123456789abcdef')
+    assert a[2:10].shares_storage_with(a)
+    assert a[2:10:1].shares_storage_with(a)
+    assert a[:].shares_storage_with(a)
+    assert not a[2:10:2].shares_storage_with(a)
+    assert not a[::-1].shares_storage_with(a)
+    assert a[2:10] == a.getslice(2, 10)
+
+def test_from_bin_and_hex_with_bytes():
+    assert BitRust.from_bin(b'1010') == BitRust.from_bin('1010')
+    assert BitRust.from_bin(bytearray(b'011')).to_bin() == '011'
+    assert BitRust.from_hex(b'0a1') == BitRust.from_hex('0a1')
+    with pytest.raises(ValueError):
+        BitRust.from_hex(b'0x\xff')
+    with pytest.raises(TypeError):
+        BitRust.from_bin(101)
+
+def test_byte_chunks():
+    a = BitRust.from_bytes(b'hello world')
+    chunks = list(a.byte_chunks(4))
+    assert chunks == [b'hell', b'o wo', b'rld']
+    assert b''.join(chunks) == a.to_bytes()
+
+def test_getitem_indices():
+    a = BitRust.from_bin('10110')
+    assert a[[0, 2, 4]].to_bin() == '110'
+    assert a[(4, 3, -5)].to_bin() == '011'
+    with pytest.raises(IndexError):
+        a[[0, 5]]
+
+def test_new():
+    assert BitRust('0x0f3') == BitRust.from_hex('0f3')
+    assert BitRust('0X0F3') == BitRust.from_hex('0f3')
+    assert BitRust('0b101') == BitRust.from_bin('101')
+    assert BitRust('0o17') == BitRust.from_oct('17')
+    assert BitRust(b'\x01\xff') == BitRust.from_bytes(b'\x01\xff')
+    assert BitRust((5, 6)).to_bin() == '000101'
+    assert BitRust((2**70 + 1, 72)).to_hex() == '400000000000000001'
+    for bad in ['101', 'abc', (16, 4), (-1, 8), 3.5]:
+        with pytest.raises(ValueError):
+            BitRust(bad)
+
+def test_from_uint():
+    assert BitRust.from_uint(1, 8).to_hex() == '01'
+    assert BitRust.from_uint(0, 0).length() == 0
+    assert BitRust.from_uint(7, 3).to_bin() == '111'
+    with pytest.raises(ValueError):
+        BitRust.from_uint(8, 3)
+
+def test_write_to():
+    import io
+    f = io.BytesIO()
+    BitRust.from_hex('0123').write_to(f)
+    BitRust.from_hex('f4567').getslice(4).write_to(f)
+    assert f.getvalue() == b'\x01\x23\x45\x67'
+    with pytest.raises(ValueError):
+        BitRust.from_bin('101').write_to(f)
+
+def test_read_from():
+    import io
+    data = bytes(range(256))
+    a = BitRust.read_from(io.BytesIO(data))
+    assert a.to_bytes() == data
+    assert BitRust.read_from(io.BytesIO(b'')).length() == 0
+    with pytest.raises(TypeError):
+        BitRust.read_from(io.StringIO('abc'))
+
+def test_eq_bytes_and_str():
+    a = BitRust.from_hex('0a')
+    assert a == b'\x0a'
+    assert a != b'\x0b'
+    assert a == '0x0a'
+    assert a == '0b00001010'
+    assert a != '0x0b'
+    assert not (a == 'not a format')
+    assert a != 10
+    assert not (BitRust.from_hex('0a1') == b'\x0a\x10')
+    assert a == BitRust.from_bin('00001010')
+    with pytest.raises(TypeError):
+        a < a
+
+def test_copy_and_typing():
+    import copy
+    import pickle
+    import typing
+    def first(values: typing.List[BitRust]) -> BitRust:
+        return copy.copy(values[0])
+    a = BitRust.from_hex('0f3a').getslice(3, 13)
+    b = first([a])
+    assert b == a
+    assert copy.deepcopy(a) == a
+    assert pickle.loads(pickle.dumps(a)) == a
+    assert BitRust[int] is not None
+
+def test_copy_shares_storage():
+    import copy
+    a = BitRust.from_hex('0f3a').getslice(3, 13)
+    b = copy.copy(a)
+    assert b == a
+    assert b.shares_storage_with(a)
+    assert not copy.deepcopy(a).shares_storage_with(a)
+
+def test_matmul_and_syndrome():
+    generator = [BitRust.from_bin(r) for r in ['1000', '0100', '0010', '0001', '1101', '1011', '0111']]
+    parity_check = [BitRust.from_bin(r) for r in ['1101100', '1011010', '0111001']]
+    codeword = BitRust.from_bin('1011').matmul(generator)
+    assert codeword.to_bin() == '1011010'
+    assert codeword.syndrome(parity_check).to_bin() == '000'
+    received = codeword.invert(0)
+    assert received.syndrome(parity_check).to_bin() == '110'
+    assert codeword.invert(6).syndrome(parity_check).to_bin() == '001'
+    with pytest.raises(ValueError):
+        codeword.syndrome(generator)
+
+def test_repr_limit():
+    a = BitRust.from_hex('ab' * 40)
+    short = repr(a)
+    assert 'length: 320' in short
+    BitRust.set_repr_limit(320)
+    try:
+        long = repr(a)
+    finally:
+        BitRust.set_repr_limit(100)
+    assert len(long) > len(short)
+    assert 'ab' * 40 in long
+    assert repr(a) == short
+    assert repr(BitRust.from_bin('101')) == 'Bits { bin: "101", length: 3 }'
+    with pytest.raises(ValueError):
+        BitRust.set_repr_limit(2 ** 63)
+    assert repr(a) == short
+    BitRust.set_repr_limit(2 ** 63 - 1)
+    try:
+        huge = repr(a)
+    finally:
+        BitRust.set_repr_limit(100)
+    assert huge == long
+
+def test_unpack_to_dict():
+    header = BitRust.from_hex('4f8a3c01ff')
+    spec = [('version', 'uint:4'), ('flags', 'bin:4'), ('delta', 'int:8'), ('tag', 'hex:8'),
+            ('ready', 'bool'), ('count', 'uint:7'), ('payload', 'bytes:1')]
+    d = header.unpack_to_dict(spec)
+    assert d == {'version': 4, 'flags': '1111', 'delta': -118, 'tag': '3c', 'ready': False,
+                 'count': 1, 'payload': b'\xff'}
+    assert list(d) == ['version', 'flags', 'delta', 'tag', 'ready', 'count', 'payload']
+    assert BitRust.from_hex('ff' * 12).unpack_to_dict([('big', 'uint:96')])['big'] == 2 ** 96 - 1
+    # As in bitstring, a bytes width is a number of bytes.
+    assert header.unpack_to_dict([('a', 'bytes:2'), ('b', 'hex:24')]) == {'a': b'\x4f\x8a', 'b': '3c01ff'}
+    with pytest.raises(ValueError):
+        header.unpack_to_dict([('a', 'bytes:6')])
+    with pytest.raises(ValueError):
+        header.unpack_to_dict([('a', 'bytes:%d' % 2 ** 62)])
+    with pytest.raises(ValueError):
+        header.unpack_to_dict([('a', 'uint:%d' % (2 ** 63 - 1)), ('b', 'uint:%d' % (2 ** 63 - 1))])
+    with pytest.raises(ValueError):
+        header.unpack_to_dict([('a', 'uint:40'), ('b', 'bool')])
+    with pytest.raises(ValueError):
+        header.unpack_to_dict([('a', 'float:32')])
+    with pytest.raises(ValueError):
+        header.unpack_to_dict([('a', 'uint:x')])
+
+def test_findall_progress():
+    a = BitRust.from_hex('81' * 100)
+    b = BitRust.from_bin('11')
+    expected = a.findall_list(b, False)
+    assert len(expected) == 99
+    reported = []
+    def record(pos):
+        reported.append(pos)
+    assert a.findall_list(b, False, progress=record, progress_interval=64) == expected
+    assert reported == list(range(64, 800, 64))
+    def stop(pos):
+        return pos < 128
+    found = a.findall_list(b, False, progress=stop, progress_interval=64)
+    assert found == [p for p in expected if p < 128]
+    assert a.findall_list(b, True, progress=record, progress_interval=7) == a.findall_list(b, True)
+    assert a.findall_list(b, False, max_matches=3, progress=record, progress_interval=5) == expected[:3]
+    with pytest.raises(ValueError):
+        a.findall_list(b, False, progress=record, progress_interval=0)
+
+def test_ifind_all():
+    a = BitRust.from_bin('1010101010')
+    b = BitRust.from_bin('101')
+    it = a.ifind_all(b, False)
+    assert iter(it) is it
+    assert next(it) == 0
+    assert next(it) == 2
+    assert list(it) == [4, 6]
+    with pytest.raises(StopIteration):
+        next(it)
+    assert list(a.ifind_all(b, False)) == a.findall_list(b, False)
+    assert list(BitRust.from_hex('00ff00ff').ifind_all(BitRust.from_hex('ff'), True)) == [8, 24]
+
+def test_getslice_open_bounds():
+    a = BitRust.from_hex('0123')
+    assert a.getslice(None, 8) == a.getslice(0, 8)
+    assert a.getslice(8, None) == a.getslice(8, 16)
+    assert a.getslice(8) == a.getslice(8, 16)
+    assert a.getslice(end_bit=4).to_bin() == '0000'
+    assert a.getslice() == a